@@ -4,7 +4,7 @@ fn main() {
 
         let bindings = bindgen::Builder::default()
             .header("wrapper.h")
-            .parse_callbacks(Box::new(bindgen::CargoCallbacks))
+            .parse_callbacks(Box::new(bindgen::CargoCallbacks::new()))
             .generate()
             .expect("Failed to generate bindings");
 
@@ -0,0 +1,254 @@
+//! Lints secrets files, so that mistakes show up before deploying them rather than as an entry
+//! that cannot be found or displayed
+
+use std::{collections::HashMap, fmt};
+
+use serde::Serialize;
+
+use crate::config::{lookup::INDEX_PREFIX, secrets::ConfigEntry, sources::ConfigSources};
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Issue {
+    pub severity: Severity,
+    /// Short machine readable identifier, e.g. `duplicate_handle`
+    pub kind: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub index: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub message: String,
+}
+
+impl Issue {
+    fn entry(
+        severity: Severity,
+        kind: &'static str,
+        index: usize,
+        entry: &ConfigEntry,
+        message: String,
+    ) -> Self {
+        Issue {
+            severity,
+            kind,
+            index: Some(index),
+            name: Some(entry.name.clone()),
+            message,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct Report {
+    /// Number of entries that could be loaded
+    pub entries: usize,
+    pub issues: Vec<Issue>,
+}
+
+impl Report {
+    pub fn severity(&self) -> Option<Severity> {
+        self.issues.iter().map(|issue| issue.severity).max()
+    }
+
+    /// 0 when the secrets are fine, 1 when there are errors, 2 when there are only warnings
+    pub fn exit_code(&self) -> i32 {
+        match self.severity() {
+            None => 0,
+            Some(Severity::Error) => 1,
+            Some(Severity::Warning) => 2,
+        }
+    }
+
+    fn count(&self, severity: Severity) -> usize {
+        self.issues
+            .iter()
+            .filter(|issue| issue.severity == severity)
+            .count()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for issue in &self.issues {
+            let severity = match issue.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+            };
+            write!(f, "{severity}[{}]", issue.kind)?;
+            if let (Some(index), Some(name)) = (issue.index, &issue.name) {
+                write!(f, " entry {index} ({name})")?;
+            }
+            writeln!(f, ": {}", issue.message)?;
+        }
+        write!(
+            f,
+            "{} entries checked, {} errors, {} warnings",
+            self.entries,
+            self.count(Severity::Error),
+            self.count(Severity::Warning)
+        )
+    }
+}
+
+/// Loads every source and checks the entries; sources that cannot be loaded are reported too
+pub async fn check_sources(sources: &ConfigSources) -> Report {
+    let loaded = sources.load_all().await;
+    let mut issues: Vec<Issue> = loaded
+        .errors
+        .iter()
+        .map(|err| Issue {
+            severity: Severity::Error,
+            kind: "unreadable",
+            index: None,
+            name: None,
+            message: format!("{err:#}"),
+        })
+        .collect();
+    issues.extend(check_entries(&loaded.entries));
+    Report {
+        entries: loaded.entries.len(),
+        issues,
+    }
+}
+
+pub fn check_entries(entries: &[ConfigEntry]) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let mut handles: HashMap<&str, usize> = HashMap::new();
+    for (index, entry) in entries.iter().enumerate() {
+        match entry.generator() {
+            Err(err) => issues.push(totp_issue(index, entry, err)),
+            Ok(generator) => {
+                // Both are checked so that a bad secret does not hide bad parameters
+                if let Err(err) = generator.key(entry) {
+                    issues.push(totp_issue(index, entry, err));
+                }
+                if let Err(err) = generator.validate(entry) {
+                    issues.push(totp_issue(index, entry, err));
+                }
+            }
+        }
+
+        let handle = entry.handle.as_str();
+        if let Ok(number) = handle.parse::<usize>()
+            && number < entries.len()
+            && number != index
+        {
+            let message = format!(
+                "Handle {handle} hides entry {number} when looking up {handle}, which then has \
+                 to be written {INDEX_PREFIX}{number}"
+            );
+            issues.push(Issue::entry(
+                Severity::Warning,
+                "numeric_handle",
+                index,
+                entry,
+                message,
+            ));
+        }
+        if !handle.is_empty() {
+            if let Some(first) = handles.get(handle) {
+                let message = format!(
+                    "Handle {handle} is already used by entry {first}, looking it up is ambiguous"
+                );
+                issues.push(Issue::entry(
+                    Severity::Error,
+                    "duplicate_handle",
+                    index,
+                    entry,
+                    message,
+                ));
+            } else {
+                handles.insert(handle, index);
+            }
+        }
+
+        #[cfg(feature = "cli")]
+        if let Some(message) = not_addressable(index) {
+            issues.push(Issue::entry(
+                Severity::Warning,
+                "not_addressable",
+                index,
+                entry,
+                message,
+            ));
+        }
+    }
+    issues
+}
+
+/// Why the console UI cannot select the entry at `index`, if it cannot
+#[cfg(feature = "cli")]
+fn not_addressable(index: usize) -> Option<String> {
+    use crate::output::cui::{ENTRY_KEYS, LOCK_KEY};
+
+    match ENTRY_KEYS.chars().nth(index) {
+        None => Some(format!(
+            "Only the first {} entries can be selected in the console UI",
+            ENTRY_KEYS.len()
+        )),
+        Some(LOCK_KEY) => {
+            Some(format!("Its key, {LOCK_KEY}, locks the console UI rather than selecting it"))
+        }
+        Some(_) => None,
+    }
+}
+
+fn totp_issue(index: usize, entry: &ConfigEntry, err: crate::totp::TotpError) -> Issue {
+    Issue::entry(Severity::Error, err.kind(), index, entry, err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_entries() {
+        let mut entries: Vec<ConfigEntry> = serde_json::from_str(
+            r#"[
+                {"name": "Fine", "handle": "mail", "secret": "JBSWY3DPEHPK3PXP"},
+                {"name": "Bad secret", "secret": "not base32!", "digits": 0},
+                {"name": "Still", "secret": "JBSWY3DPEHPK3PXP", "timestep": 0},
+                {"name": "Numeric", "handle": "1", "secret": "JBSWY3DPEHPK3PXP"},
+                {"name": "Again", "handle": "mail", "secret": "JBSWY3DPEHPK3PXP"}
+            ]"#,
+        )
+        .unwrap();
+        let kinds = |issues: Vec<Issue>| -> Vec<(usize, &'static str)> {
+            issues
+                .iter()
+                .map(|issue| (issue.index.unwrap(), issue.kind))
+                .collect()
+        };
+        assert_eq!(
+            kinds(check_entries(&entries)),
+            [
+                (1, "malformed_secret"),
+                (1, "unsupported_digits"),
+                (2, "zero_timestep"),
+                (3, "numeric_handle"),
+                (4, "duplicate_handle"),
+            ]
+        );
+
+        entries.truncate(1);
+        assert!(check_entries(&entries).is_empty());
+        // The console UI selects entries with one key each, up to p, and l locks it instead
+        #[cfg(feature = "cli")]
+        {
+            entries.resize(crate::output::cui::ENTRY_KEYS.len() + 1, entries[0].clone());
+            for entry in entries.iter_mut() {
+                entry.handle.clear();
+            }
+            assert_eq!(
+                kinds(check_entries(&entries)),
+                [(21, "not_addressable"), (26, "not_addressable")]
+            );
+        }
+    }
+}
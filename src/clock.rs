@@ -0,0 +1,90 @@
+use std::{
+    fmt::Debug,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Source of the current time, so that token generation and the UI timers can be driven by
+/// something other than the system clock
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> SystemTime;
+
+    fn unix_timestamp(&self) -> u64 {
+        self.now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Clock is set before the UNIX epoch")
+            .as_secs()
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// Shifts another clock by a fixed number of seconds, for devices whose clock is known to be off
+#[derive(Debug)]
+pub struct OffsetClock<C: Clock> {
+    inner: C,
+    offset_seconds: i64,
+}
+
+impl<C: Clock> OffsetClock<C> {
+    pub fn new(inner: C, offset_seconds: i64) -> Self {
+        OffsetClock {
+            inner,
+            offset_seconds,
+        }
+    }
+}
+
+impl<C: Clock> Clock for OffsetClock<C> {
+    fn now(&self) -> SystemTime {
+        let offset = Duration::from_secs(self.offset_seconds.unsigned_abs());
+        if self.offset_seconds >= 0 {
+            self.inner.now() + offset
+        } else {
+            self.inner.now() - offset
+        }
+    }
+}
+
+#[cfg(test)]
+pub use fake::FakeClock;
+
+#[cfg(test)]
+mod fake {
+    use std::{
+        sync::Mutex,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+
+    use super::Clock;
+
+    /// Clock that only moves when told to
+    #[derive(Debug)]
+    pub struct FakeClock {
+        now: Mutex<SystemTime>,
+    }
+
+    impl FakeClock {
+        pub fn at(unix_timestamp: u64) -> Self {
+            FakeClock {
+                now: Mutex::new(UNIX_EPOCH + Duration::from_secs(unix_timestamp)),
+            }
+        }
+
+        pub fn advance(&self, by: Duration) {
+            *self.now.lock().unwrap() += by;
+        }
+    }
+
+    impl Clock for FakeClock {
+        fn now(&self) -> SystemTime {
+            *self.now.lock().unwrap()
+        }
+    }
+}
@@ -1,6 +1,12 @@
-use clap::{ArgAction, Parser, Subcommand, ValueEnum};
+use clap::{ArgAction, Args as ClapArgs, Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
 
+use crate::{
+    config::format::SecretsFormat,
+    skew::TimeSource,
+    totp::{Algorithm, OtpType, SecretEncoding},
+};
+
 #[derive(Clone, ValueEnum, PartialEq, Eq, Debug)]
 pub enum NumberStyle {
     Standard,
@@ -27,10 +33,100 @@ pub struct Args {
     )]
     pub std_err: bool,
 
+    /// Seconds added to the system clock before generating or verifying codes (may be negative)
+    #[arg(long, env = "TOTP_CLOCK_OFFSET", default_value_t = 0, allow_negative_numbers = true)]
+    pub clock_offset: i64,
+
+    /// File holding the passphrase of an encrypted secrets file, used when TOTP_PASSPHRASE is not
+    /// set; otherwise the passphrase is prompted for
+    #[arg(long, env = "TOTP_PASSPHRASE_FILE")]
+    pub passphrase_file: Option<PathBuf>,
+
+    /// Format of the secrets file, by default guessed from its extension (.toml, .yaml or .yml,
+    /// anything else is JSON). TOML files keep their comments when written back (e.g. to advance
+    /// a counter or edit entries); YAML files with comments cannot be written back
+    #[arg(long, value_enum, env = "TOTP_SECRETS_FORMAT")]
+    pub secrets_format: Option<SecretsFormat>,
+
+    /// Settings file giving the defaults of the interface options, by default
+    /// ~/.config/totp-generator/config.toml when it exists
+    #[arg(long, env = "TOTP_CONFIG")]
+    pub config: Option<PathBuf>,
+
     #[command(subcommand)]
     pub mode: Mode,
 }
 
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Print the options that can be set in the settings file as they would be without flags, and
+    /// where each comes from
+    Show,
+}
+
+/// Fields of a new entry; anything left out takes the value from `uri`, a prompt or the default
+#[derive(ClapArgs, Debug)]
+pub struct EntryArgs {
+    /// otpauth:// URI, as found in the QR code shown by the service; other flags override it
+    #[arg(long)]
+    pub uri: Option<String>,
+
+    /// Display name, prompted for when missing
+    #[arg(long)]
+    pub name: Option<String>,
+
+    /// Secret, prompted for without echo when missing (which keeps it out of shell history)
+    #[arg(long)]
+    pub secret: Option<String>,
+
+    /// How the secret is written: base32 (default), hex, base64 or ascii
+    #[arg(long, value_parser = parse_encoding)]
+    pub encoding: Option<SecretEncoding>,
+
+    #[arg(long)]
+    pub handle: Option<String>,
+
+    #[arg(long)]
+    pub issuer: Option<String>,
+
+    #[arg(long)]
+    pub account: Option<String>,
+
+    /// May be repeated
+    #[arg(long = "tag")]
+    pub tags: Vec<String>,
+
+    #[arg(long)]
+    pub notes: Option<String>,
+
+    /// totp, hotp, steam, motp or a registered type
+    #[arg(long = "type", value_parser = parse_otp_type)]
+    pub otp_type: Option<OtpType>,
+
+    /// PIN of schemes such as mOTP, prompted for without echo when the type needs one
+    #[arg(long)]
+    pub pin: Option<String>,
+
+    /// SHA1, SHA256 or SHA512
+    #[arg(long, value_parser = parse_algorithm)]
+    pub algorithm: Option<Algorithm>,
+
+    #[arg(long)]
+    pub digits: Option<u8>,
+
+    /// Time step in seconds
+    #[arg(long)]
+    pub timestep: Option<u64>,
+
+    /// UNIX timestamp from which time steps are counted
+    #[arg(long)]
+    pub t0: Option<u64>,
+
+    /// Initial counter of a HOTP entry
+    #[arg(long)]
+    pub counter: Option<u64>,
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
 pub enum Origin {
     GoogleAuthenticator,
@@ -44,23 +140,39 @@ pub enum Mode {
         #[arg(required = true)]
         target: String,
 
-        /// Path to secrets TOML file
-        #[arg(short, long, env = "TOTP_SECRETS")]
-        secrets: String,
+        /// Path to a secrets file (JSON, TOML or YAML) or a directory of them; may be repeated. In
+        /// TOTP_SECRETS, several paths are separated as in PATH
+        #[arg(short, long, env = "TOTP_SECRETS", required = true)]
+        secrets: Vec<String>,
+
+        /// Increment the counter of a HOTP entry before generating its code; the target must then
+        /// be a handle, #index or index
+        #[arg(long, action = ArgAction::SetTrue)]
+        advance: bool,
+
+        /// Also print the code of the next time step
+        #[arg(long, action = ArgAction::SetTrue)]
+        next: bool,
+
+        /// Only consider entries with this tag; an index then counts tagged entries only
+        #[arg(long)]
+        tag: Option<String>,
     },
 
     /// Run the console UI and/or HTTP interface
     Interface {
-        /// Path to secrets JSON file
-        #[arg(short, long, env = "TOTP_SECRETS")]
-        secrets: String,
+        /// Path to a secrets file (JSON, TOML or YAML) or a directory of them; may be repeated. In
+        /// TOTP_SECRETS, several paths are separated as in PATH
+        #[arg(short, long, env = "TOTP_SECRETS", required = true)]
+        secrets: Vec<String>,
 
         /// Bind HTTP server to this address (e.g. 127.0.0.1)
         #[arg(short, long)]
         bind: Option<String>,
 
         /// Disable the Console UI
-        #[arg(short, long, action = ArgAction::SetTrue)]
+        #[arg(short, long, num_args = 0..=1, require_equals = true, default_value_t = false,
+            default_missing_value = "true", action = ArgAction::Set)]
         no_console: bool,
 
         /// Port to serve HTTP on (default: 3000)
@@ -71,9 +183,201 @@ pub enum Mode {
         #[arg(short, long, default_value_t = 300)]
         lock_after: u16,
 
+        /// Password unlocking the UI once locked; without it any key unlocks
+        #[arg(long, env = "UNLOCK_PASSWORD", hide_env_values = true)]
+        unlock_password: Option<String>,
+
         /// One of: standard, pipe, lite, utf8
         #[arg(long, value_enum, default_value_t = NumberStyle::Standard)]
         number_style: NumberStyle,
+
+        /// Show the next code during the last N seconds of each time step (0 to disable)
+        #[arg(long, default_value_t = 5)]
+        show_next: u16,
+
+        /// Number of time steps before and after the current one accepted by POST /verify
+        #[arg(long, default_value_t = 1)]
+        verify_window: u8,
+
+        /// Reference time used to detect a wrong clock, e.g. sntp://pool.ntp.org or
+        /// http://192.168.1.1
+        #[arg(long)]
+        time_source: Option<TimeSource>,
+
+        /// Only show entries with this tag in the console UI, press `/` to change it
+        #[arg(long)]
+        tag: Option<String>,
+
+        /// Skew in seconds above which a warning is shown
+        #[arg(long, default_value_t = 5)]
+        max_clock_skew: u64,
+
+        /// Seconds between two clock skew checks
+        #[arg(long, default_value_t = 600)]
+        skew_check_interval: u64,
+
+        /// Check the secrets file for changes on every refresh rather than relying on file system
+        /// notifications (e.g. on network mounts that do not deliver them)
+        #[arg(long, num_args = 0..=1, require_equals = true, default_value_t = false,
+            default_missing_value = "true", action = ArgAction::Set)]
+        no_watch: bool,
+    },
+
+    /// Check a submitted code against an entry
+    ///
+    /// The time step of the accepted code is saved in the secrets file, so that the code, or an
+    /// older one, is refused afterwards
+    Verify {
+        /// The handle, #index or index of the entry to verify against
+        #[arg(required = true)]
+        target: String,
+
+        /// The code to verify
+        #[arg(required = true)]
+        code: String,
+
+        /// Path to a secrets file (JSON, TOML or YAML) or a directory of them; may be repeated. In
+        /// TOTP_SECRETS, several paths are separated as in PATH
+        #[arg(short, long, env = "TOTP_SECRETS", required = true)]
+        secrets: Vec<String>,
+
+        /// Number of time steps before and after the current one to accept
+        #[arg(long, default_value_t = 1)]
+        window: u8,
+    },
+
+    /// Report mistakes in secrets files; exits with 1 on errors and 2 on warnings only
+    Check {
+        /// Path to a secrets file (JSON, TOML or YAML) or a directory of them; may be repeated. In
+        /// TOTP_SECRETS, several paths are separated as in PATH
+        #[arg(short, long, env = "TOTP_SECRETS", required = true)]
+        secrets: Vec<String>,
+
+        /// Print the report as JSON
+        #[arg(long, action = ArgAction::SetTrue)]
+        json: bool,
+    },
+
+    /// Encrypt a plaintext secrets file in place
+    Encrypt {
+        /// Path to secrets file (JSON, TOML or YAML)
+        #[arg(short, long, env = "TOTP_SECRETS")]
+        secrets: String,
+    },
+
+    /// Decrypt an encrypted secrets file in place
+    Decrypt {
+        /// Path to secrets file (JSON, TOML or YAML)
+        #[arg(short, long, env = "TOTP_SECRETS")]
+        secrets: String,
+    },
+
+    /// Change the passphrase of an encrypted secrets file
+    Rekey {
+        /// Path to secrets file (JSON, TOML or YAML)
+        #[arg(short, long, env = "TOTP_SECRETS")]
+        secrets: String,
+
+        /// File holding the new passphrase, used when TOTP_NEW_PASSPHRASE is not set
+        #[arg(long, env = "TOTP_NEW_PASSPHRASE_FILE")]
+        new_passphrase_file: Option<PathBuf>,
+    },
+
+    /// Add an entry to a secrets file, from flags, an otpauth:// URI or prompts for what is missing
+    Add {
+        /// Path to secrets file (JSON, TOML or YAML)
+        #[arg(short, long, env = "TOTP_SECRETS")]
+        secrets: String,
+
+        #[command(flatten)]
+        entry: EntryArgs,
+    },
+
+    /// Remove an entry from a secrets file
+    Remove {
+        /// The entry to remove: its handle, #index or index
+        #[arg(required = true)]
+        target: String,
+
+        /// Path to secrets file (JSON, TOML or YAML)
+        #[arg(short, long, env = "TOTP_SECRETS")]
+        secrets: String,
+    },
+
+    /// Change the name of an entry
+    Rename {
+        /// The entry to rename: its handle, #index or index
+        #[arg(required = true)]
+        target: String,
+
+        /// The new name
+        #[arg(required = true)]
+        name: String,
+
+        /// Path to secrets file (JSON, TOML or YAML)
+        #[arg(short, long, env = "TOTP_SECRETS")]
+        secrets: String,
+    },
+
+    /// Change the handle of an entry, an empty handle removes it
+    SetHandle {
+        /// The entry to change: its handle, #index or index
+        #[arg(required = true)]
+        target: String,
+
+        /// The new handle
+        #[arg(required = true)]
+        handle: String,
+
+        /// Path to secrets file (JSON, TOML or YAML)
+        #[arg(short, long, env = "TOTP_SECRETS")]
+        secrets: String,
+    },
+
+    /// List the entries, without their secrets unless --reveal is given
+    List {
+        /// Path to a secrets file (JSON, TOML or YAML) or a directory of them; may be repeated. In
+        /// TOTP_SECRETS, several paths are separated as in PATH
+        #[arg(short, long, env = "TOTP_SECRETS", required = true)]
+        secrets: Vec<String>,
+
+        /// Only list entries with this tag
+        #[arg(long)]
+        tag: Option<String>,
+
+        /// Print JSON instead of a table
+        #[arg(long, action = ArgAction::SetTrue)]
+        json: bool,
+
+        /// Include the secrets
+        #[arg(long, action = ArgAction::SetTrue)]
+        reveal: bool,
+    },
+
+    /// Print every field of an entry, without its secret unless --reveal is given
+    Show {
+        /// The entry to show
+        #[arg(required = true)]
+        target: String,
+
+        /// Path to a secrets file (JSON, TOML or YAML) or a directory of them; may be repeated. In
+        /// TOTP_SECRETS, several paths are separated as in PATH
+        #[arg(short, long, env = "TOTP_SECRETS", required = true)]
+        secrets: Vec<String>,
+
+        /// Print JSON instead of text
+        #[arg(long, action = ArgAction::SetTrue)]
+        json: bool,
+
+        /// Include the secret and PIN
+        #[arg(long, action = ArgAction::SetTrue)]
+        reveal: bool,
+    },
+
+    /// Inspect the settings file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
     },
 
     /// Import a secret config from a QR code image
@@ -95,3 +399,32 @@ pub enum Mode {
         origin: Origin,
     },
 }
+
+impl Mode {
+    /// Secrets paths of the subcommands that accept several of them
+    pub fn secrets_mut(&mut self) -> Option<&mut Vec<String>> {
+        match self {
+            Mode::OneTime { secrets, .. }
+            | Mode::Interface { secrets, .. }
+            | Mode::Verify { secrets, .. }
+            | Mode::Check { secrets, .. }
+            | Mode::List { secrets, .. }
+            | Mode::Show { secrets, .. } => Some(secrets),
+            _ => None,
+        }
+    }
+}
+
+fn parse_otp_type(value: &str) -> Result<OtpType, String> {
+    serde_json::from_value(value.to_ascii_lowercase().into()).map_err(|err| err.to_string())
+}
+
+fn parse_encoding(value: &str) -> Result<SecretEncoding, String> {
+    serde_json::from_value(value.to_ascii_lowercase().into())
+        .map_err(|_| format!("unsupported encoding {value}, expected base32, hex, base64 or ascii"))
+}
+
+fn parse_algorithm(value: &str) -> Result<Algorithm, String> {
+    serde_json::from_value(value.to_ascii_uppercase().into())
+        .map_err(|_| format!("unsupported algorithm {value}, expected SHA1, SHA256 or SHA512"))
+}
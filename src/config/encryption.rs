@@ -0,0 +1,188 @@
+//! Passphrase protected secrets files: the plaintext JSON is sealed with XChaCha20-Poly1305 under
+//! a key derived from the passphrase with Argon2id, and stored in a small JSON envelope that keeps
+//! everything needed to decrypt it again
+
+use std::{env, path::Path};
+
+use anyhow::{Context, Result, anyhow, bail};
+use argon2::{Argon2, Params};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use chacha20poly1305::{
+    AeadCore, KeyInit, XChaCha20Poly1305, XNonce,
+    aead::{Aead, OsRng, rand_core::RngCore},
+};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::secret::Secret;
+
+/// Value of the `format` field that identifies an encrypted secrets file
+const FORMAT: &str = "totp-generator/encrypted";
+const VERSION: u8 = 1;
+const KDF: &str = "argon2id";
+const CIPHER: &str = "xchacha20poly1305";
+const SALT_LENGTH: usize = 16;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Envelope {
+    format: String,
+    version: u8,
+    kdf: KdfParams,
+    cipher: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// Argon2id costs are stored alongside the salt so that they can be raised without breaking
+/// existing files
+#[derive(Debug, Serialize, Deserialize)]
+struct KdfParams {
+    algorithm: String,
+    salt: String,
+    memory_kib: u32,
+    iterations: u32,
+    parallelism: u32,
+}
+
+impl KdfParams {
+    fn generate() -> Self {
+        let mut salt = [0u8; SALT_LENGTH];
+        OsRng.fill_bytes(&mut salt);
+        KdfParams {
+            algorithm: KDF.to_owned(),
+            salt: STANDARD.encode(salt),
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        }
+    }
+
+    fn derive_key(&self, passphrase: &Secret<String>) -> Result<Zeroizing<[u8; 32]>> {
+        if self.algorithm != KDF {
+            bail!("Unsupported key derivation {}", self.algorithm);
+        }
+        let salt = STANDARD.decode(&self.salt).context("Invalid salt")?;
+        let params = Params::new(self.memory_kib, self.iterations, self.parallelism, Some(32))
+            .map_err(|err| anyhow!("Invalid key derivation parameters: {err}"))?;
+        let mut key = Zeroizing::new([0u8; 32]);
+        Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params)
+            .hash_password_into(passphrase.expose().as_bytes(), &salt, key.as_mut())
+            .map_err(|err| anyhow!("Failed to derive key: {err}"))?;
+        Ok(key)
+    }
+}
+
+/// Whether `content` is an encrypted secrets file rather than a plaintext list of entries
+pub fn is_encrypted(content: &str) -> bool {
+    serde_json::from_str::<Envelope>(content).is_ok_and(|envelope| envelope.format == FORMAT)
+}
+
+/// Seals the plaintext secrets JSON under `passphrase`, with a fresh salt and nonce
+pub fn encrypt(plaintext: &str, passphrase: &Secret<String>) -> Result<String> {
+    let kdf = KdfParams::generate();
+    let key = kdf.derive_key(passphrase)?;
+    let cipher = XChaCha20Poly1305::new(key.as_ref().into());
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext.as_bytes())
+        .map_err(|_| anyhow!("Failed to encrypt secrets"))?;
+    let envelope = Envelope {
+        format: FORMAT.to_owned(),
+        version: VERSION,
+        kdf,
+        cipher: CIPHER.to_owned(),
+        nonce: STANDARD.encode(nonce),
+        ciphertext: STANDARD.encode(ciphertext),
+    };
+    Ok(serde_json::to_string_pretty(&envelope)?)
+}
+
+/// Opens an encrypted secrets file; a wrong passphrase and a tampered file are indistinguishable
+pub fn decrypt(content: &str, passphrase: &Secret<String>) -> Result<Zeroizing<String>> {
+    let envelope: Envelope =
+        serde_json::from_str(content).context("Not an encrypted secrets file")?;
+    if envelope.format != FORMAT {
+        bail!("Not an encrypted secrets file");
+    }
+    if envelope.version != VERSION {
+        bail!("Unsupported encrypted secrets file version {}", envelope.version);
+    }
+    if envelope.cipher != CIPHER {
+        bail!("Unsupported cipher {}", envelope.cipher);
+    }
+    let key = envelope.kdf.derive_key(passphrase)?;
+    let nonce = STANDARD.decode(&envelope.nonce).context("Invalid nonce")?;
+    if nonce.len() != 24 {
+        bail!("Invalid nonce");
+    }
+    let ciphertext = STANDARD
+        .decode(&envelope.ciphertext)
+        .context("Invalid ciphertext")?;
+    let plaintext = XChaCha20Poly1305::new(key.as_ref().into())
+        .decrypt(XNonce::from_slice(&nonce), ciphertext.as_slice())
+        .map_err(|_| anyhow!("Wrong passphrase or corrupted secrets file"))?;
+    let plaintext = Zeroizing::new(plaintext);
+    Ok(Zeroizing::new(
+        String::from_utf8(plaintext.to_vec()).context("Decrypted secrets are not UTF-8")?,
+    ))
+}
+
+/// Passphrase taken from the `env_var` environment variable, else the first line of
+/// `passphrase_file`, else asked on the terminal (twice when `confirm` is set)
+pub fn read_passphrase(
+    env_var: &str,
+    passphrase_file: Option<&Path>,
+    prompt: &str,
+    confirm: bool,
+) -> Result<Secret<String>> {
+    if let Ok(passphrase) = env::var(env_var) {
+        return Ok(Secret::new(passphrase));
+    }
+    if let Some(path) = passphrase_file {
+        let content = Zeroizing::new(
+            std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read passphrase from {}", path.display()))?,
+        );
+        let passphrase = content.lines().next().unwrap_or_default();
+        if passphrase.is_empty() {
+            bail!("Passphrase file {} is empty", path.display());
+        }
+        return Ok(Secret::new(passphrase.to_owned()));
+    }
+    let passphrase = prompt_passphrase(prompt, env_var)?;
+    if passphrase.expose().is_empty() {
+        bail!("Passphrase cannot be empty");
+    }
+    if confirm && prompt_passphrase("Confirm passphrase: ", env_var)? != passphrase {
+        bail!("Passphrases do not match");
+    }
+    Ok(passphrase)
+}
+
+fn prompt_passphrase(prompt: &str, env_var: &str) -> Result<Secret<String>> {
+    rpassword::prompt_password(prompt)
+        .map(Secret::new)
+        .with_context(|| format!("Failed to read a passphrase from the terminal, set {env_var}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypted_roundtrip() {
+        let plaintext = r#"[{"name": "Test", "secret": "JBSWY3DPEHPK3PXP"}]"#;
+        let passphrase = Secret::new("correct horse".to_owned());
+        let encrypted = encrypt(plaintext, &passphrase).unwrap();
+        assert!(is_encrypted(&encrypted));
+        assert!(!is_encrypted(plaintext));
+        assert!(!encrypted.contains("JBSWY3DPEHPK3PXP"));
+        assert_eq!(decrypt(&encrypted, &passphrase).unwrap().as_str(), plaintext);
+
+        let wrong = Secret::new("battery staple".to_owned());
+        assert!(decrypt(&encrypted, &wrong).is_err());
+
+        // Salt and nonce are fresh every time
+        assert_ne!(encrypt(plaintext, &passphrase).unwrap(), encrypted);
+    }
+}
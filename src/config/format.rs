@@ -0,0 +1,266 @@
+//! On-disk formats of the secrets file. JSON and YAML files are a list of entries, TOML files an
+//! array of `[[entries]]` tables; all of them share the `ConfigEntry` schema
+
+use std::{fmt, path::Path};
+
+use anyhow::{Result, anyhow, bail};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use toml_edit::{ArrayOfTables, DocumentMut, Item, Table};
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum SecretsFormat {
+    #[default]
+    Json,
+    Toml,
+    Yaml,
+}
+
+/// Entry as written in the file, so that fields unknown to this version survive a rewrite
+#[derive(Debug, Clone)]
+pub struct RawEntry {
+    /// Position in the file it was read from, `None` for an entry being added
+    pub origin: Option<usize>,
+    pub value: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct TomlFile<T> {
+    #[serde(default = "Vec::new")]
+    entries: Vec<T>,
+}
+
+impl SecretsFormat {
+    /// Format implied by the file extension; anything unknown is read as JSON
+    pub fn from_path(path: &str) -> Self {
+        let extension = Path::new(path)
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("toml") => SecretsFormat::Toml,
+            Some("yaml" | "yml") => SecretsFormat::Yaml,
+            _ => SecretsFormat::Json,
+        }
+    }
+
+    /// Content of a file without entries
+    pub fn empty(&self) -> &'static str {
+        match self {
+            SecretsFormat::Json | SecretsFormat::Yaml => "[]\n",
+            SecretsFormat::Toml => "",
+        }
+    }
+
+    /// Entries of the file, either as `ConfigEntry` or as raw values when fields unknown to this
+    /// version must survive a rewrite
+    pub fn parse<T: DeserializeOwned>(&self, content: &str) -> Result<Vec<T>> {
+        Ok(match self {
+            SecretsFormat::Json => serde_json::from_str(content)?,
+            SecretsFormat::Toml => toml::from_str::<TomlFile<T>>(content)?.entries,
+            SecretsFormat::Yaml => serde_yaml::from_str(content)?,
+        })
+    }
+
+    /// Raw entries of the file, each knowing where it came from
+    pub fn parse_raw(&self, content: &str) -> Result<Vec<RawEntry>> {
+        let values: Vec<serde_json::Value> = self.parse(content)?;
+        Ok(values
+            .into_iter()
+            .enumerate()
+            .map(|(index, value)| RawEntry {
+                origin: Some(index),
+                value,
+            })
+            .collect())
+    }
+
+    /// Serialises raw entries back to this format. TOML files are edited in place so that their
+    /// comments and layout survive, following their entries when they move; JSON and YAML files
+    /// are rewritten, so YAML files with comments are refused rather than losing them
+    pub fn serialize(&self, entries: &[RawEntry], original: &str) -> Result<String> {
+        let values = || entries.iter().map(|entry| &entry.value).collect::<Vec<_>>();
+        match self {
+            SecretsFormat::Json => Ok(serde_json::to_string_pretty(&values())?),
+            SecretsFormat::Toml => update_toml(original, entries),
+            SecretsFormat::Yaml if has_yaml_comments(original) => bail!(
+                "Cannot write to a YAML secrets file with comments, they would be lost; remove \
+                 them or convert the file to TOML, which keeps them"
+            ),
+            SecretsFormat::Yaml => Ok(serde_yaml::to_string(&values())?),
+        }
+    }
+}
+
+impl fmt::Display for SecretsFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SecretsFormat::Json => write!(f, "JSON"),
+            SecretsFormat::Toml => write!(f, "TOML"),
+            SecretsFormat::Yaml => write!(f, "YAML"),
+        }
+    }
+}
+
+/// Whether a YAML document has comments: a `#` at the start of a line or after a space, outside
+/// of quotes. Quotes are only followed within a line, and only when they start a value
+fn has_yaml_comments(content: &str) -> bool {
+    content.lines().any(|line| {
+        let mut quote = None;
+        let mut previous = ' ';
+        let mut chars = line.chars().peekable();
+        while let Some(ch) = chars.next() {
+            match (quote, ch) {
+                (None, '#') if previous.is_whitespace() => return true,
+                (None, '\'' | '"') if previous.is_whitespace() || "-:[{,".contains(previous) => {
+                    quote = Some(ch)
+                }
+                // Escaped as `\"` within double quotes and as `''` within single ones
+                (Some('"'), '\\') => {
+                    chars.next();
+                }
+                (Some('\''), '\'') if chars.peek() == Some(&'\'') => {
+                    chars.next();
+                }
+                (Some(open), ch) if ch == open => quote = None,
+                _ => {}
+            }
+            previous = ch;
+        }
+        false
+    })
+}
+
+/// Rebuilds the `[[entries]]` of the original document from `entries`, each starting from the
+/// table it was read from (with its comments) and only touching the fields whose value changed
+fn update_toml(original: &str, entries: &[RawEntry]) -> Result<String> {
+    let mut document: DocumentMut = original.parse()?;
+    let previous: Vec<serde_json::Value> = SecretsFormat::Toml.parse(original)?;
+    let tables = document
+        .entry("entries")
+        .or_insert(Item::ArrayOfTables(ArrayOfTables::new()))
+        .as_array_of_tables_mut()
+        .ok_or(anyhow!("`entries` must be an array of tables"))?;
+    let existing: Vec<Table> = tables.iter().cloned().collect();
+    // Tables are laid out by position, reusing them in order keeps moved entries where they go
+    let positions: Vec<usize> = existing.iter().filter_map(Table::position).collect();
+    let mut last_position = None;
+    let mut rebuilt = ArrayOfTables::new();
+    for (index, entry) in entries.iter().enumerate() {
+        let serde_json::Value::Object(fields) = &entry.value else {
+            bail!("Entry {index} is not a table");
+        };
+        let origin = entry.origin.filter(|origin| *origin < existing.len());
+        let mut table = match origin {
+            Some(origin) => existing[origin].clone(),
+            None => {
+                let mut table = Table::new();
+                if index > 0 {
+                    table.decor_mut().set_prefix("\n");
+                }
+                table
+            }
+        };
+        if let Some(position) = positions.get(index).copied().or(last_position.map(|p| p + 1)) {
+            table.set_position(position);
+            last_position = Some(position);
+        }
+        let before = origin.and_then(|origin| previous.get(origin));
+        let stale: Vec<String> = table
+            .iter()
+            .map(|(key, _)| key.to_owned())
+            .filter(|key| !fields.contains_key(key))
+            .collect();
+        for key in stale {
+            table.remove(&key);
+        }
+        for (key, value) in fields {
+            if before.and_then(|entry| entry.get(key)) == Some(value) {
+                continue;
+            }
+            let mut value = value.serialize(toml_edit::ser::ValueSerializer::new())?;
+            // Keep comments at the end of the line
+            if let Some(old) = table.get(key).and_then(Item::as_value) {
+                *value.decor_mut() = old.decor().clone();
+            }
+            table.insert(key, Item::Value(value));
+        }
+        rebuilt.push(table);
+    }
+    *tables = rebuilt;
+    Ok(document.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::secrets::ConfigEntry;
+
+    #[test]
+    fn test_formats() {
+        let toml = r#"
+# Shared with the ops team
+[[entries]]
+name = "Work Email"
+handle = "gmail"
+secret = "JBSWY3DPEHPK3PXP" # rotated yearly
+
+[[entries]]
+name = "VPN"
+secret = "GEZDGNBVGY3TQOJQ"
+type = "hotp"
+"#;
+        let yaml = "
+# Shared with the ops team
+- name: Work Email
+  handle: gmail
+  secret: JBSWY3DPEHPK3PXP
+- name: VPN
+  secret: GEZDGNBVGY3TQOJQ
+  type: hotp
+";
+        let json = r#"[
+            {"name": "Work Email", "handle": "gmail", "secret": "JBSWY3DPEHPK3PXP"},
+            {"name": "VPN", "secret": "GEZDGNBVGY3TQOJQ", "type": "hotp"}
+        ]"#;
+        for (path, content) in [("s.toml", toml), ("s.YML", yaml), ("s.json", json)] {
+            let format = SecretsFormat::from_path(path);
+            let entries: Vec<ConfigEntry> = format.parse(content).unwrap();
+            assert_eq!(entries.len(), 2, "{format}");
+            assert_eq!(entries[0].handle, "gmail", "{format}");
+            assert_eq!(entries[1].secret.expose(), "GEZDGNBVGY3TQOJQ", "{format}");
+
+            // Commented YAML files are not written back, see below
+            let content = content.replace("# Shared with the ops team\n", "");
+            let mut raw = format.parse_raw(&content).unwrap();
+            raw[1].value["counter"] = 3.into();
+            let written = format.serialize(&raw, &content).unwrap();
+            let entries: Vec<ConfigEntry> = format.parse(&written).unwrap();
+            assert_eq!(entries[1].counter, 3, "{format}");
+        }
+
+        // Comments survive in TOML files
+        let mut raw = SecretsFormat::Toml.parse_raw(toml).unwrap();
+        raw[1].value["counter"] = 1.into();
+        let written = SecretsFormat::Toml.serialize(&raw, toml).unwrap();
+        assert!(written.contains("# Shared with the ops team"), "{written}");
+        assert!(written.contains("# rotated yearly"), "{written}");
+
+        // and follow their entry when it moves
+        raw.swap(0, 1);
+        let written = SecretsFormat::Toml.serialize(&raw, toml).unwrap();
+        let entries: Vec<ConfigEntry> = SecretsFormat::Toml.parse(&written).unwrap();
+        assert_eq!(entries[0].name, "VPN");
+        let comment = written.find("# rotated yearly").unwrap();
+        assert!(written.find("name = \"VPN\"").unwrap() < comment, "{written}");
+
+        // YAML ones would be lost
+        let raw = SecretsFormat::Yaml.parse_raw(yaml).unwrap();
+        let err = SecretsFormat::Yaml.serialize(&raw, yaml).unwrap_err();
+        assert!(err.to_string().contains("comments"), "{err}");
+        assert!(has_yaml_comments("- name: Mail\n  secret: JBSWY3DPEHPK3PXP # personal\n"));
+        assert!(has_yaml_comments("- name: Bob's mail # personal\n"));
+        assert!(!has_yaml_comments(
+            "- name: \"C# team\"\n  notes: 'it''s #1'\n  account: me#work\n  tags: [\"a\\\" #b\"]\n"
+        ));
+    }
+}
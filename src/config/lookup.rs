@@ -0,0 +1,196 @@
+//! Finds the entry a user means from what they typed: `#3` for an index, a handle, a name, the
+//! start of either, or letters of the name in order (e.g. `ghb` for GitHub). Anything that changes
+//! an entry or verifies a code against it uses [`find_exact`] instead, so that a typo cannot pick
+//! another entry
+
+use std::fmt;
+
+use super::secrets::ConfigEntry;
+
+/// Marks an explicit index, e.g. `#3`, so that it is never mistaken for a handle
+pub const INDEX_PREFIX: char = '#';
+
+/// Candidates listed in an ambiguity error before the rest are elided
+const MAX_CANDIDATES: usize = 5;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LookupError {
+    NotFound(String),
+    /// Several entries match equally well; lists them as `#index name (handle)`
+    Ambiguous {
+        query: String,
+        candidates: Vec<String>,
+    },
+}
+
+impl fmt::Display for LookupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LookupError::NotFound(query) => write!(f, "No entry matches {query}"),
+            LookupError::Ambiguous { query, candidates } => {
+                write!(f, "{query} matches {} entries: ", candidates.len())?;
+                let listed = candidates.len().min(MAX_CANDIDATES);
+                write!(f, "{}", candidates[..listed].join(", "))?;
+                if candidates.len() > listed {
+                    write!(f, " and {} more", candidates.len() - listed)?;
+                }
+                write!(f, "; use a handle or {INDEX_PREFIX}index")
+            }
+        }
+    }
+}
+
+impl std::error::Error for LookupError {}
+
+/// Index of the entry matching `query`. Each way of matching is only tried when the previous ones
+/// found nothing: `#index`, exact handle, bare index (for compatibility), name ignoring case,
+/// handle or name prefix ignoring case, then fuzzy match on the name. Several matches at the same
+/// step are an error rather than a guess
+pub fn find(entries: &[ConfigEntry], query: &str) -> Result<usize, LookupError> {
+    let not_found = || LookupError::NotFound(query.to_owned());
+    if let Some(index) = exact(entries, query)? {
+        return Ok(index);
+    }
+    if query.starts_with(INDEX_PREFIX) || query.parse::<usize>().is_ok() {
+        return Err(not_found());
+    }
+    let matching = |matches: &dyn Fn(&ConfigEntry) -> bool| -> Vec<usize> {
+        (0..entries.len())
+            .filter(|&index| matches(&entries[index]))
+            .collect()
+    };
+
+    let lowercase = query.to_lowercase();
+    let names = matching(&|entry| entry.name.to_lowercase() == lowercase);
+    if !names.is_empty() {
+        return single(entries, query, names);
+    }
+    let prefixes = matching(&|entry| {
+        entry.handle.to_lowercase().starts_with(&lowercase)
+            || entry.name.to_lowercase().starts_with(&lowercase)
+    });
+    if !prefixes.is_empty() {
+        return single(entries, query, prefixes);
+    }
+
+    let scores: Vec<(usize, usize)> = entries
+        .iter()
+        .enumerate()
+        .filter_map(|(index, entry)| fuzzy_score(&lowercase, &entry.name).map(|s| (index, s)))
+        .collect();
+    let best = scores
+        .iter()
+        .map(|(_, score)| *score)
+        .max()
+        .ok_or_else(not_found)?;
+    let fuzzy = scores
+        .into_iter()
+        .filter(|(_, score)| *score == best)
+        .map(|(index, _)| index)
+        .collect();
+    single(entries, query, fuzzy)
+}
+
+/// Index of the entry `query` names exactly: `#index`, its handle, or a bare index
+pub fn find_exact(entries: &[ConfigEntry], query: &str) -> Result<usize, LookupError> {
+    exact(entries, query)?.ok_or_else(|| LookupError::NotFound(query.to_owned()))
+}
+
+/// The first steps of [`find`], `None` when they match nothing
+fn exact(entries: &[ConfigEntry], query: &str) -> Result<Option<usize>, LookupError> {
+    let in_range = |index: usize| (index < entries.len()).then_some(index);
+    if let Some(index) = query.strip_prefix(INDEX_PREFIX) {
+        return Ok(index.parse().ok().and_then(in_range));
+    }
+    let handles: Vec<usize> = (0..entries.len())
+        .filter(|&index| entries[index].handle == query)
+        .collect();
+    if !handles.is_empty() {
+        return single(entries, query, handles).map(Some);
+    }
+    Ok(query.parse().ok().and_then(in_range))
+}
+
+fn single(entries: &[ConfigEntry], query: &str, found: Vec<usize>) -> Result<usize, LookupError> {
+    match found.as_slice() {
+        [index] => Ok(*index),
+        _ => Err(LookupError::Ambiguous {
+            query: query.to_owned(),
+            candidates: found
+                .into_iter()
+                .map(|index| describe(index, &entries[index]))
+                .collect(),
+        }),
+    }
+}
+
+fn describe(index: usize, entry: &ConfigEntry) -> String {
+    if entry.handle.is_empty() {
+        format!("{INDEX_PREFIX}{index} {}", entry.name)
+    } else {
+        format!("{INDEX_PREFIX}{index} {} ({})", entry.name, entry.handle)
+    }
+}
+
+/// Scores `name` when it contains every character of `query` (already lowercase) in order.
+/// Characters following the previous match or starting a word score more, so `ghb` prefers
+/// "GitHub Backup" to "Graphite Hub"
+fn fuzzy_score(query: &str, name: &str) -> Option<usize> {
+    let name: Vec<char> = name.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous: Option<usize> = None;
+    for wanted in query.chars() {
+        let found = position + name[position..].iter().position(|ch| *ch == wanted)?;
+        score += 1;
+        if previous.is_some_and(|previous| previous + 1 == found) {
+            score += 2;
+        }
+        if found == 0 || !name[found - 1].is_alphanumeric() {
+            score += 2;
+        }
+        previous = Some(found);
+        position = found + 1;
+    }
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find() {
+        let entries: Vec<ConfigEntry> = serde_json::from_str(
+            r#"[
+                {"name": "GitHub", "handle": "gh", "secret": "JBSWY3DPEHPK3PXP"},
+                {"name": "AWS", "handle": "awsp", "secret": "JBSWY3DPEHPK3PXP"},
+                {"name": "AWS", "handle": "awss", "secret": "JBSWY3DPEHPK3PXP"},
+                {"name": "Google Mail", "handle": "0", "secret": "JBSWY3DPEHPK3PXP"}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(find(&entries, "#0"), Ok(0));
+        assert_eq!(find(&entries, "0"), Ok(3));
+        assert_eq!(find(&entries, "2"), Ok(2));
+        assert_eq!(find(&entries, "awss"), Ok(2));
+        assert_eq!(find(&entries, "github"), Ok(0));
+        assert_eq!(find(&entries, "goo"), Ok(3));
+        assert_eq!(find(&entries, "gml"), Ok(3));
+        assert_eq!(
+            find(&entries, "#4"),
+            Err(LookupError::NotFound("#4".to_owned()))
+        );
+        assert_eq!(
+            find(&entries, "aws").unwrap_err().to_string(),
+            "aws matches 2 entries: #1 AWS (awsp), #2 AWS (awss); use a handle or #index"
+        );
+        assert!(find(&entries, "xyz").is_err());
+
+        assert_eq!(find_exact(&entries, "awsp"), Ok(1));
+        assert_eq!(find_exact(&entries, "#3"), Ok(3));
+        assert_eq!(find_exact(&entries, "2"), Ok(2));
+        assert!(find_exact(&entries, "github").is_err());
+        assert!(find_exact(&entries, "gml").is_err());
+    }
+}
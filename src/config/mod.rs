@@ -1,2 +1,10 @@
 pub mod secrets;
+pub mod sources;
 pub mod configuration;
+pub mod encryption;
+pub mod format;
+pub mod lookup;
+pub mod otpauth;
+pub mod resolver;
+pub mod settings;
+pub mod watcher;
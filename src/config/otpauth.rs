@@ -0,0 +1,127 @@
+//! `otpauth://` URIs, as encoded in the QR codes services show when enabling two-factor
+//! authentication, e.g. `otpauth://totp/GitHub:alice?secret=JBSWY3DPEHPK3PXP&issuer=GitHub`
+
+use anyhow::{Context, Result, anyhow, bail};
+
+use super::secrets::ConfigEntry;
+use crate::totp::{Algorithm, OtpType};
+
+const SCHEME: &str = "otpauth://";
+
+/// Builds an entry from the URI. The label gives the account, prefixed by the issuer unless it is
+/// given as a parameter; the entry is named after the issuer, or the account when there is none
+pub fn parse(uri: &str) -> Result<ConfigEntry> {
+    let rest = uri
+        .get(..SCHEME.len())
+        .filter(|scheme| scheme.eq_ignore_ascii_case(SCHEME))
+        .map(|_| &uri[SCHEME.len()..])
+        .ok_or(anyhow!("Not an otpauth:// URI"))?;
+    let (path, query) = rest.split_once('?').unwrap_or((rest, ""));
+    let (otp_type, label) = path.split_once('/').unwrap_or((path, ""));
+    let label = decode(label, false)?;
+    let (label_issuer, account) = split_label(&label);
+
+    let mut secret = None;
+    let mut issuer = None;
+    let mut algorithm = Algorithm::default();
+    let mut digits = None;
+    let mut period = None;
+    let mut counter = None;
+    for parameter in query.split('&').filter(|parameter| !parameter.is_empty()) {
+        let (key, value) = parameter.split_once('=').unwrap_or((parameter, ""));
+        let value = decode(value, true)?;
+        match key.to_ascii_lowercase().as_str() {
+            "secret" => secret = Some(value),
+            "issuer" => issuer = Some(value),
+            "algorithm" => {
+                algorithm = serde_json::from_value(value.to_ascii_uppercase().into())
+                    .map_err(|_| anyhow!("Unsupported algorithm {value}"))?
+            }
+            "digits" => digits = Some(value.parse().context("Invalid digits")?),
+            "period" => period = Some(value.parse().context("Invalid period")?),
+            "counter" => counter = Some(value.parse().context("Invalid counter")?),
+            _ => tracing::debug!("Ignoring otpauth parameter {key}"),
+        }
+    }
+
+    let secret = secret.ok_or(anyhow!("The URI has no secret"))?;
+    let issuer = issuer.or(label_issuer).filter(|issuer| !issuer.is_empty());
+    let account = Some(account).filter(|account| !account.is_empty());
+    let name = match (&issuer, &account) {
+        (Some(issuer), _) => issuer.clone(),
+        (None, Some(account)) => account.clone(),
+        (None, None) => bail!("The URI has neither an issuer nor an account"),
+    };
+    let mut entry = ConfigEntry::new(name, secret);
+    entry.otp_type = serde_json::from_value(otp_type.to_ascii_lowercase().into())?;
+    if entry.otp_type == OtpType::Hotp {
+        entry.counter = counter.unwrap_or_default();
+    }
+    entry.issuer = issuer;
+    entry.account = account;
+    entry.algorithm = algorithm;
+    entry.digits = digits.unwrap_or(entry.digits);
+    entry.timestep = period.unwrap_or(entry.timestep);
+    Ok(entry)
+}
+
+/// Splits a label such as `GitHub:alice` into the issuer it may start with and the account
+pub fn split_label(label: &str) -> (Option<String>, String) {
+    match label.split_once(':') {
+        Some((issuer, account)) => (Some(issuer.trim().to_owned()), account.trim().to_owned()),
+        None => (None, label.trim().to_owned()),
+    }
+}
+
+/// Undoes percent encoding, and `+` for spaces in query parameters
+fn decode(value: &str, query: bool) -> Result<String> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut rest = value.bytes();
+    while let Some(byte) = rest.next() {
+        match byte {
+            b'%' => {
+                let hex = [rest.next(), rest.next()];
+                let [Some(high), Some(low)] = hex else {
+                    bail!("Truncated percent encoding in {value}");
+                };
+                let hex = std::str::from_utf8(&[high, low])?.to_owned();
+                bytes.push(
+                    u8::from_str_radix(&hex, 16)
+                        .with_context(|| format!("Invalid percent encoding in {value}"))?,
+                );
+            }
+            b'+' if query => bytes.push(b' '),
+            byte => bytes.push(byte),
+        }
+    }
+    Ok(String::from_utf8(bytes)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let entry = parse(
+            "otpauth://totp/ACME%20Co:john.doe@email.com?secret=HXDMVJECJJWSRB3HWIZR4IFUGFTMXBOZ\
+             &issuer=ACME+Co&algorithm=SHA256&digits=8&period=60",
+        )
+        .unwrap();
+        assert_eq!(entry.name, "ACME Co");
+        assert_eq!(entry.issuer.as_deref(), Some("ACME Co"));
+        assert_eq!(entry.account.as_deref(), Some("john.doe@email.com"));
+        assert_eq!(entry.secret.expose(), "HXDMVJECJJWSRB3HWIZR4IFUGFTMXBOZ");
+        assert_eq!(
+            (entry.algorithm, entry.digits, entry.timestep),
+            (Algorithm::Sha256, 8, 60)
+        );
+
+        let entry = parse("otpauth://hotp/alice?secret=JBSWY3DPEHPK3PXP&counter=7").unwrap();
+        assert_eq!((entry.name.as_str(), entry.issuer), ("alice", None));
+        assert_eq!((entry.otp_type, entry.counter), (OtpType::Hotp, 7));
+
+        assert!(parse("otpauth://totp/alice").is_err());
+        assert!(parse("https://example.com/?secret=JBSWY3DPEHPK3PXP").is_err());
+    }
+}
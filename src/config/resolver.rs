@@ -0,0 +1,185 @@
+//! Secrets kept outside the secrets file: in an environment variable, in a file (e.g. a mounted
+//! Kubernetes secret) or printed by a command (e.g. `pass show totp/github`)
+
+use std::{
+    collections::HashMap,
+    env, fmt,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use zeroize::Zeroizing;
+
+use crate::{secret::Secret, totp::TotpError};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SecretSource {
+    Env(String),
+    /// Relative paths are taken from the directory of the secrets file
+    File(PathBuf),
+    /// Run with `sh -c` from the directory of the secrets file
+    Command(String),
+}
+
+impl fmt::Display for SecretSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SecretSource::Env(name) => write!(f, "environment variable {name}"),
+            SecretSource::File(path) => write!(f, "file {}", path.display()),
+            SecretSource::Command(command) => write!(f, "command `{command}`"),
+        }
+    }
+}
+
+impl SecretSource {
+    /// Reads the secret; only the first line of files and command output is used, so that `pass`
+    /// entries with extra lines and files ending with a newline work as they are
+    pub fn resolve(&self, base: &Path) -> Result<Secret<String>, TotpError> {
+        let fail = |reason: String| TotpError::UnresolvedSecret(format!("{self}: {reason}"));
+        let content = match self {
+            SecretSource::Env(name) => {
+                Zeroizing::new(env::var(name).map_err(|_| fail("not set".to_owned()))?)
+            }
+            SecretSource::File(path) => Zeroizing::new(
+                std::fs::read_to_string(base.join(path)).map_err(|err| fail(err.to_string()))?,
+            ),
+            SecretSource::Command(command) => {
+                let output = Command::new("sh")
+                    .arg("-c")
+                    .arg(command)
+                    .current_dir(base)
+                    .stdin(Stdio::null())
+                    .output()
+                    .map_err(|err| fail(err.to_string()))?;
+                let stdout = Zeroizing::new(output.stdout);
+                if !output.status.success() {
+                    let stderr = String::from_utf8_lossy(&output.stderr);
+                    let reason = match stderr.lines().next() {
+                        Some(line) => format!("{} {line}", output.status),
+                        None => output.status.to_string(),
+                    };
+                    return Err(fail(reason));
+                }
+                Zeroizing::new(
+                    String::from_utf8(stdout.to_vec())
+                        .map_err(|_| fail("output is not UTF-8".to_owned()))?,
+                )
+            }
+        };
+        let secret = content.lines().next().unwrap_or_default().trim();
+        if secret.is_empty() {
+            return Err(fail("empty".to_owned()));
+        }
+        Ok(Secret::new(secret.to_owned()))
+    }
+
+    /// Modification time of a file source, `None` for other sources or when it cannot be read
+    fn modified(&self, base: &Path) -> Option<SystemTime> {
+        match self {
+            SecretSource::File(path) => std::fs::metadata(base.join(path)).ok()?.modified().ok(),
+            _ => None,
+        }
+    }
+}
+
+/// Secrets resolved for the entries of one secrets file, so that commands are not run on every
+/// refresh. They are kept until the secrets file is reloaded, except that files are read again
+/// once modified (e.g. a rotated Kubernetes secret); failures are not kept and are retried the
+/// next time the secret is needed
+#[derive(Debug)]
+pub struct SecretCache {
+    base: PathBuf,
+    values: Mutex<HashMap<SecretSource, Cached>>,
+}
+
+#[derive(Debug)]
+struct Cached {
+    secret: Secret<String>,
+    /// Modification time of a file source when it was read
+    modified: Option<SystemTime>,
+}
+
+impl SecretCache {
+    /// `secrets_path` is the secrets file, relative sources are resolved from its directory
+    pub fn new(secrets_path: &str) -> Self {
+        let base = match Path::new(secrets_path).parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        SecretCache {
+            base,
+            values: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn resolve(&self, source: &SecretSource) -> Result<Secret<String>, TotpError> {
+        let modified = source.modified(&self.base);
+        if let Some(cached) = self.values.lock().unwrap().get(source)
+            // A file that cannot be looked at, e.g. while being replaced, keeps its last value
+            && (modified.is_none() || cached.modified == modified)
+        {
+            return Ok(cached.secret.clone());
+        }
+        // Not holding the lock while a command runs, at worst it runs twice
+        let secret = source.resolve(&self.base)?;
+        let cached = Cached {
+            secret: secret.clone(),
+            modified,
+        };
+        self.values.lock().unwrap().insert(source.clone(), cached);
+        Ok(secret)
+    }
+
+    /// Forgets every secret, so that they are resolved again when next needed
+    pub fn clear(&self) {
+        self.values.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_sources() {
+        let directory = tempfile::tempdir().unwrap();
+        let directory = directory.path();
+        std::fs::write(directory.join("github"), "JBSWY3DPEHPK3PXP\n").unwrap();
+        let cache = SecretCache::new(&directory.join("secrets.json").to_string_lossy());
+
+        let file = SecretSource::File("github".into());
+        assert_eq!(cache.resolve(&file).unwrap().expose(), "JBSWY3DPEHPK3PXP");
+        let command = SecretSource::Command("cat github; echo 'url: github.com'".to_owned());
+        assert_eq!(cache.resolve(&command).unwrap().expose(), "JBSWY3DPEHPK3PXP");
+
+        // Read again once modified, as when a mounted secret is rotated
+        let rotated = std::fs::File::options()
+            .write(true)
+            .open(directory.join("github"))
+            .unwrap();
+        std::fs::write(directory.join("github"), "GEZDGNBVGY3TQOJQ\n").unwrap();
+        rotated
+            .set_modified(SystemTime::now() + std::time::Duration::from_secs(60))
+            .unwrap();
+        assert_eq!(cache.resolve(&file).unwrap().expose(), "GEZDGNBVGY3TQOJQ");
+        assert_eq!(cache.resolve(&command).unwrap().expose(), "JBSWY3DPEHPK3PXP");
+        cache.clear();
+        assert_eq!(cache.resolve(&command).unwrap().expose(), "GEZDGNBVGY3TQOJQ");
+        assert_eq!(cache.resolve(&file).unwrap().expose(), "GEZDGNBVGY3TQOJQ");
+
+        // Kept once resolved
+        std::fs::remove_file(directory.join("github")).unwrap();
+        assert_eq!(cache.resolve(&file).unwrap().expose(), "GEZDGNBVGY3TQOJQ");
+
+        let failing = SecretSource::Command("echo locked >&2; exit 2".to_owned());
+        let err = cache.resolve(&failing).unwrap_err().to_string();
+        assert!(err.contains("exit status: 2 locked"), "{err}");
+        let missing = SecretSource::Env("TOTP_TEST_SURELY_UNSET".to_owned());
+        assert_eq!(
+            cache.resolve(&missing).unwrap_err().to_string(),
+            "Cannot read the secret from environment variable TOTP_TEST_SURELY_UNSET: not set"
+        );
+    }
+}
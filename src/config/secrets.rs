@@ -1,27 +1,115 @@
-use std::{ops::Deref, sync::Arc, time::SystemTime};
+use std::{
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::SystemTime,
+};
 
-use anyhow::{Context, Result, anyhow};
+use anyhow::{Context, Result, anyhow, bail};
 use serde::{Deserialize, Serialize};
-use tokio::{fs, sync::RwLock};
+use tokio::{
+    fs,
+    io::AsyncWriteExt,
+    sync::{RwLock, watch},
+};
+use zeroize::Zeroizing;
 
+use super::{
+    encryption,
+    format::{RawEntry, SecretsFormat},
+    lookup,
+    resolver::{SecretCache, SecretSource},
+};
+use crate::{
+    clock::Clock,
+    generator::{self, OtpGenerator},
+    secret::Secret,
+    totp::{self, Algorithm, OtpType, SecretEncoding, TimeStep, Totp, TotpError},
+    verify::{Verification, Verifier},
+};
+
+/// One account of the secrets file
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct ConfigEntry {
     pub name: String,
     #[serde(default = "empty_string")]
     pub handle: String,
-    pub secret: String,
+    /// Use [`ConfigEntry::secret`] to read it, the secret may be kept elsewhere
+    #[serde(default, skip_serializing_if = "is_empty_secret")]
+    pub secret: Secret<String>,
+    /// Environment variable holding the secret, instead of `secret`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret_env: Option<String>,
+    /// File whose first line is the secret, relative to the secrets file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret_file: Option<PathBuf>,
+    /// Shell command printing the secret on its first line, e.g. `pass show totp/github`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret_command: Option<String>,
+    #[serde(default)]
+    pub encoding: SecretEncoding,
     #[serde(default = "default_step")]
-    pub timestep: u16,
+    pub timestep: u64,
+    /// UNIX timestamp from which time steps are counted (RFC 6238 T0)
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub t0: u64,
     #[serde(default = "default_digits")]
     pub digits: u8,
+    #[serde(default)]
+    pub algorithm: Algorithm,
+    #[serde(default, rename = "type")]
+    pub otp_type: OtpType,
+    /// Moving factor of HOTP entries, ignored for TOTP
+    #[serde(default)]
+    pub counter: u64,
+    /// Time step of the last code accepted by `verify`, codes up to it are not accepted again
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_verified: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<String>,
+    /// Account at the issuer, e.g. the user name or email address
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+    /// Free-form labels used to filter entries, e.g. `work` or `prod`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Free-form text for the user, e.g. where the recovery codes are kept; never served over HTTP
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    /// PIN mixed into the token by schemes such as mOTP
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pin: Option<Secret<String>>,
+    /// Prefix of the secrets file the entry was read from, when several are merged
+    #[serde(skip)]
+    pub source: Option<String>,
+    /// Set by [`ConfigFile`] so that secrets kept elsewhere are only read once
+    #[serde(skip)]
+    secret_cache: Option<Arc<SecretCache>>,
 }
 
+/// What can be shared about an entry, i.e. everything but its secret, PIN and notes
 #[derive(Serialize)]
 pub struct ConfigEntryPublic<'a> {
     pub name: &'a str,
     pub code: &'a str,
-    pub timestep: u16,
+    pub timestep: u64,
+    pub t0: u64,
     pub digits: u8,
+    pub algorithm: Algorithm,
+    #[serde(rename = "type")]
+    pub otp_type: &'a OtpType,
+    pub counter: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub account: Option<&'a str>,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    pub tags: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<&'a str>,
 }
 
 impl<'a> From<&'a ConfigEntry> for ConfigEntryPublic<'a> {
@@ -30,9 +118,87 @@ impl<'a> From<&'a ConfigEntry> for ConfigEntryPublic<'a> {
             name: &entry.name,
             code: &entry.handle,
             timestep: entry.timestep,
+            t0: entry.t0,
             digits: entry.digits,
+            algorithm: entry.algorithm,
+            otp_type: &entry.otp_type,
+            counter: entry.counter,
+            issuer: entry.issuer.as_deref(),
+            account: entry.account.as_deref(),
+            tags: &entry.tags,
+            source: entry.source.as_deref(),
+        }
+    }
+}
+
+impl ConfigEntry {
+    /// Current token for this entry, either time based or from the stored counter
+    pub fn totp(&self, clock: &dyn Clock) -> Result<Totp, TotpError> {
+        generator::generate(self, clock)
+    }
+
+    /// Time steps as configured by `timestep` and `t0`, for generators that use them
+    pub fn time_step(&self) -> TimeStep {
+        TimeStep {
+            t0: self.t0,
+            seconds: self.timestep,
+        }
+    }
+
+    /// Generator registered for the entry's type
+    pub fn generator(&self) -> Result<Arc<dyn OtpGenerator>, TotpError> {
+        generator::lookup(&self.otp_type)
+    }
+
+    /// Where the secret is kept, if not in the entry itself
+    pub fn secret_source(&self) -> Result<Option<SecretSource>, TotpError> {
+        let mut sources = [
+            self.secret_env.clone().map(SecretSource::Env),
+            self.secret_file.clone().map(SecretSource::File),
+            self.secret_command.clone().map(SecretSource::Command),
+        ]
+        .into_iter()
+        .flatten();
+        let source = sources.next();
+        if sources.next().is_some() || (source.is_some() && !self.secret.expose().is_empty()) {
+            return Err(TotpError::ConflictingSecrets);
+        }
+        Ok(source)
+    }
+
+    /// The secret, as written in the entry or read from where it is kept
+    pub fn secret(&self) -> Result<Secret<String>, TotpError> {
+        match self.secret_source()? {
+            None => Ok(self.secret.clone()),
+            Some(source) => match &self.secret_cache {
+                Some(cache) => cache.resolve(&source),
+                None => source.resolve(Path::new(".")),
+            },
+        }
+    }
+
+    /// Key decoded from the secret according to its encoding
+    pub fn key(&self) -> Result<Secret<Vec<u8>>, TotpError> {
+        self.generator()?.key(self)
+    }
+
+    pub fn validate(&self) -> Result<(), TotpError> {
+        let generator = self.generator()?;
+        generator.key(self)?;
+        generator.validate(self)
+    }
+
+    /// Applies the leniency of the secrets file, e.g. lowercase base32 secrets with spaces
+    fn normalize(&mut self) {
+        if self.encoding == SecretEncoding::Base32 {
+            self.secret = Secret::new(totp::normalize_base32(self.secret.expose()));
         }
     }
+
+    /// Tags are compared ignoring case, so `Work` and `work` select the same entries
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|own| own.eq_ignore_ascii_case(tag))
+    }
 }
 
 fn default_digits() -> u8 {
@@ -43,19 +209,43 @@ fn empty_string() -> String {
     "".to_owned()
 }
 
-fn default_step() -> u16 {
+fn default_step() -> u64 {
     30
 }
 
-#[cfg(feature = "configure")]
+fn is_zero(value: &u64) -> bool {
+    *value == 0
+}
+
+fn is_empty_secret(secret: &Secret<String>) -> bool {
+    secret.expose().is_empty()
+}
+
 impl ConfigEntry {
+    /// Time based entry with the default parameters
     pub fn new(name: String, secret: String) -> Self {
         ConfigEntry {
             name,
             handle: empty_string(),
-            secret,
+            secret: Secret::new(secret),
+            encoding: SecretEncoding::default(),
             timestep: default_step(),
+            t0: 0,
             digits: default_digits(),
+            algorithm: Algorithm::default(),
+            otp_type: OtpType::default(),
+            counter: 0,
+            issuer: None,
+            account: None,
+            tags: Vec::new(),
+            notes: None,
+            pin: None,
+            source: None,
+            secret_env: None,
+            secret_file: None,
+            secret_command: None,
+            last_verified: None,
+            secret_cache: None,
         }
     }
 }
@@ -64,32 +254,195 @@ impl ConfigEntry {
 pub struct ConfigData {
     pub entries: Vec<ConfigEntry>,
     last_modified: SystemTime,
+    /// Modification time of the file as we last wrote it, so that our own writes are not reloaded
+    written: Option<SystemTime>,
 }
 
+/// What subscribers of a [`ConfigFile`] are notified of
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    /// The file was changed by someone else and its entries reloaded
+    Reloaded,
+    /// This process edited the file, e.g. advanced a counter; its entries are already up to date
+    Edited,
+}
+
+/// Previous versions of the secrets file kept by default, see [`ConfigFile::with_backups`]
+pub const DEFAULT_BACKUPS: usize = 3;
+
+/// Secrets file on disk along with the entries last read from it; it is reloaded by
+/// [`ConfigFile::load`] whenever it changes. Its format follows the file extension unless set with
+/// [`ConfigFile::with_format`]. Encrypted files are read with the passphrase given
+/// to [`ConfigFile::with_passphrase`] and stay encrypted when written back
+///
+/// While a [`watcher`](super::watcher) is running, the entries are reloaded when the file changes
+/// instead, and subscribers are notified
+///
+/// Entries are edited with [`ConfigFile::add_entry`], [`ConfigFile::update_entry`],
+/// [`ConfigFile::remove_entry`] and [`ConfigFile::move_entry`]
 #[derive(Debug)]
 pub struct ConfigFile {
     pub secrets_path: String,
+    format: SecretsFormat,
+    passphrase: Option<Secret<String>>,
+    backups: usize,
     data: Arc<RwLock<ConfigData>>,
+    secret_cache: Arc<SecretCache>,
+    watched: AtomicBool,
+    changes: watch::Sender<Change>,
+    /// Plaintext decrypted by [`ConfigFile::unlock`] and the modification time of the file it came
+    /// from, used instead of decrypting again while the file is unchanged
+    unlocked: std::sync::Mutex<Option<(SystemTime, Secret<String>)>>,
 }
 
 impl ConfigFile {
     pub fn new(secrets_path: String) -> Self {
         ConfigFile {
+            format: SecretsFormat::from_path(&secrets_path),
+            secret_cache: Arc::new(SecretCache::new(&secrets_path)),
             secrets_path,
+            passphrase: None,
+            backups: DEFAULT_BACKUPS,
             data: Arc::new(RwLock::new(ConfigData {
                 entries: Vec::new(),
                 last_modified: SystemTime::UNIX_EPOCH,
+                written: None,
             })),
+            watched: AtomicBool::new(false),
+            changes: watch::Sender::new(Change::Edited),
+            unlocked: std::sync::Mutex::new(None),
+        }
+    }
+
+    pub fn with_format(mut self, format: SecretsFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn with_passphrase(mut self, passphrase: Secret<String>) -> Self {
+        self.passphrase = Some(passphrase);
+        self
+    }
+
+    /// Number of previous versions kept as `<file>.1` (the latest) to `<file>.<backups>` whenever
+    /// entries are edited, 0 to keep none
+    pub fn with_backups(mut self, backups: usize) -> Self {
+        self.backups = backups;
+        self
+    }
+
+    /// Creates the secrets file without any entry unless it exists, returns whether it did. On
+    /// unix it is only readable by its owner, which edits then keep
+    pub async fn create(&self) -> Result<bool> {
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let created = options.open(&self.secrets_path).await;
+        match created {
+            Ok(mut file) => {
+                file.write_all(self.format.empty().as_bytes()).await?;
+                Ok(true)
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => Ok(false),
+            Err(err) => Err(err)
+                .with_context(|| format!("Failed to create config file at {}", self.secrets_path)),
         }
     }
 
-    async fn load_secrets(secrets_path: &str) -> Result<Vec<ConfigEntry>> {
-        let content = fs::read_to_string(secrets_path)
+    /// Whether the secrets file on disk is encrypted
+    pub async fn is_encrypted(&self) -> Result<bool> {
+        let content = self.read_file().await?;
+        Ok(encryption::is_encrypted(&content))
+    }
+
+    async fn read_file(&self) -> Result<Zeroizing<String>> {
+        let content = fs::read_to_string(&self.secrets_path)
             .await
-            .with_context(|| format!("Failed to read config file at {}", secrets_path))?;
+            .with_context(|| format!("Failed to read config file at {}", self.secrets_path))?;
+        Ok(Zeroizing::new(content))
+    }
 
-        let parsed: Vec<ConfigEntry> = serde_json::from_str(&content)
-            .with_context(|| format!("Failed to parse secrets from {}", secrets_path))?;
+    /// Uses `passphrase` if it decrypts the secrets file, returns whether it did, so that another
+    /// one can be asked for before loading it
+    pub async fn unlock(&mut self, passphrase: &Secret<String>) -> Result<bool> {
+        let modified = fs::metadata(&self.secrets_path).await?.modified()?;
+        let content = self.read_file().await?;
+        let Ok(plaintext) = encryption::decrypt(&content, passphrase) else {
+            return Ok(false);
+        };
+        self.passphrase = Some(passphrase.clone());
+        // Deriving the key is slow on purpose, the first load should not do it again
+        *self.unlocked.get_mut().unwrap() = Some((modified, Secret::new(plaintext.to_string())));
+        Ok(true)
+    }
+
+    /// Plaintext JSON of the secrets file, and whether it had to be decrypted
+    async fn read_plaintext(&self) -> Result<(Zeroizing<String>, bool)> {
+        let unlocked = self.unlocked.lock().unwrap().take();
+        if let Some((modified, plaintext)) = unlocked
+            && fs::metadata(&self.secrets_path).await?.modified()? == modified
+        {
+            return Ok((Zeroizing::new(plaintext.expose().clone()), true));
+        }
+        let content = self.read_file().await?;
+        if !encryption::is_encrypted(&content) {
+            return Ok((content, false));
+        }
+        let passphrase = self.passphrase.as_ref().ok_or(anyhow!(
+            "{} is encrypted, a passphrase is required",
+            self.secrets_path
+        ))?;
+        let plaintext = encryption::decrypt(&content, passphrase)
+            .with_context(|| format!("Failed to decrypt {}", self.secrets_path))?;
+        Ok((plaintext, true))
+    }
+
+    /// Writes the secrets file back, encrypted under `passphrase` or in plaintext when `None`
+    pub async fn rewrite(&self, passphrase: Option<&Secret<String>>) -> Result<()> {
+        let _data = self.data.write().await;
+        let _lock = self.lock().await?;
+        let (plaintext, _) = self.read_plaintext().await?;
+        // Refuse to seal something that could not be loaded afterwards
+        self.format
+            .parse::<serde_json::Value>(&plaintext)
+            .with_context(|| format!("Failed to parse secrets from {}", self.secrets_path))?;
+        let content = match passphrase {
+            Some(passphrase) => encryption::encrypt(&plaintext, passphrase)?,
+            None => plaintext.to_string(),
+        };
+        write_atomically(&self.secrets_path, content).await
+    }
+
+    async fn load_secrets(&self) -> Result<Vec<ConfigEntry>> {
+        // The file may now point somewhere else, or say why the secrets changed
+        self.secret_cache.clear();
+        let (content, _) = self.read_plaintext().await?;
+        self.parse_entries(&content)
+    }
+
+    fn parse_entries(&self, content: &str) -> Result<Vec<ConfigEntry>> {
+        let secrets_path = &self.secrets_path;
+        let mut parsed: Vec<ConfigEntry> = self.format.parse(content).with_context(|| {
+            format!("Failed to parse {} secrets from {}", self.format, secrets_path)
+        })?;
+        for entry in parsed.iter_mut() {
+            entry.normalize();
+            entry.secret_cache = Some(Arc::clone(&self.secret_cache));
+        }
+        // Invalid entries are kept so that each output can report them individually rather than
+        // losing the whole file over one typo
+        for (index, entry) in parsed.iter().enumerate() {
+            let valid = match entry.secret_source() {
+                // Secrets kept elsewhere are only read once needed, e.g. not for every entry in
+                // one-time mode
+                Ok(Some(_)) => entry.generator().and_then(|generator| generator.validate(entry)),
+                _ => entry.validate(),
+            };
+            if let Err(err) = valid {
+                tracing::warn!("Entry {index} ({}) in {secrets_path} is invalid: {err}", entry.name);
+            }
+        }
 
         Ok(parsed)
     }
@@ -103,7 +456,70 @@ impl ConfigFile {
         Ok(guard.last_modified < metadata_modified)
     }
 
+    /// Notified whenever a watcher reloads the entries, whether or not that succeeded, and
+    /// whenever this process edits the file
+    pub fn subscribe(&self) -> watch::Receiver<Change> {
+        self.changes.subscribe()
+    }
+
+    pub fn is_watched(&self) -> bool {
+        self.watched.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_watched(&self, watched: bool) {
+        self.watched.store(watched, Ordering::Relaxed);
+    }
+
+    /// Rereads the secrets file whatever its modification time, then notifies subscribers. After a
+    /// failure the next [`ConfigFile::load`] goes back to the file so that it reports the error.
+    /// Returns false when the file is left alone because it is as this process last wrote it
+    pub async fn reload(&self) -> Result<bool> {
+        {
+            let data = self.data.read().await;
+            let modified = fs::metadata(&self.secrets_path)
+                .await
+                .and_then(|metadata| metadata.modified());
+            if let (Some(written), Ok(modified)) = (data.written, modified)
+                && written == modified
+            {
+                tracing::debug!("{} is as we last wrote it", self.secrets_path);
+                return Ok(false);
+            }
+        }
+        let loaded = self.load_secrets().await;
+        let result = {
+            let mut data = self.data.write().await;
+            match loaded {
+                Ok(entries) => {
+                    data.entries = entries;
+                    data.last_modified = SystemTime::now();
+                    data.written = None;
+                    Ok(true)
+                }
+                Err(err) => {
+                    data.last_modified = SystemTime::UNIX_EPOCH;
+                    Err(err)
+                }
+            }
+        };
+        self.changes.send_replace(Change::Reloaded);
+        result
+    }
+
+    /// Entries as last loaded, without looking at the file
+    pub async fn entries(&self) -> Vec<ConfigEntry> {
+        self.data.read().await.entries.clone()
+    }
+
+    /// Returns the entries, and whether they were reloaded from disk since the previous call
     pub async fn load(&self) -> Result<(bool, Vec<ConfigEntry>)> {
+        if self.is_watched() {
+            let data = self.data.read().await;
+            // The watcher keeps the entries current, no need to look at the file
+            if data.last_modified != SystemTime::UNIX_EPOCH {
+                return Ok((false, data.entries.clone()));
+            }
+        }
         // First check that we believe the file has been modified (relies on metadata)
         let mut has_been_modified = {
             let data = self.data.read().await;
@@ -119,7 +535,7 @@ impl ConfigFile {
                 "Config file {} has been modified, reloading",
                 self.secrets_path
             );
-            let entries = Self::load_secrets(&self.secrets_path).await?;
+            let entries = self.load_secrets().await?;
             let mut data = self.data.write().await;
             // Since we conducted some reading file/parsing, there is a small chance of race
             // condition where there was a more recent update, so we check once more with a Write
@@ -135,12 +551,513 @@ impl ConfigFile {
         Ok((has_been_modified, self.data.read().await.entries.clone()))
     }
 
-    pub fn get_secret(secrets: &[ConfigEntry], arg: &str) -> Result<ConfigEntry> {
-        let entry = if let Ok(index) = arg.parse::<usize>() {
-            secrets.get(index).cloned()
-        } else {
-            secrets.iter().find(|e| e.handle == arg).cloned()
+    /// Increments the counter of the counter based (e.g. HOTP) entry `loaded`, which was at `index`
+    /// when the file was loaded, and persists it to the secrets file
+    pub async fn advance_counter(&self, index: usize, loaded: &ConfigEntry) -> Result<ConfigEntry> {
+        // Edits hold the write lock, which serialises concurrent advances (e.g. UI and HTTP) so
+        // that no counter value is handed out twice
+        let find = |entries: &[ConfigEntry]| find_loaded(entries, index, loaded);
+        let (index, entry, ()) = self
+            .edit_entry(find, |entry| {
+                if entry.generator()?.time_step(entry).is_some() {
+                    bail!("Entry {} does not use a counter", entry.name);
+                }
+                entry.counter += 1;
+                Ok(())
+            })
+            .await?;
+        tracing::info!("Counter of {} advanced to {}", entry.name, entry.counter);
+        Ok(self.entries().await.swap_remove(index))
+    }
+
+    /// Checks `code` against the entry `loaded` (see [`Self::advance_counter`]) and records the
+    /// time step it was accepted for in the secrets file, so that neither this process nor a later
+    /// one accepts it again
+    pub async fn verify(
+        &self,
+        index: usize,
+        loaded: &ConfigEntry,
+        code: &str,
+        verifier: &Verifier,
+    ) -> Result<Verification> {
+        let find = |entries: &[ConfigEntry]| find_loaded(entries, index, loaded);
+        let (_, _, outcome) = self
+            .edit_entry(find, |entry| {
+                let outcome = verifier.verify(entry, code)?;
+                if let Verification::Accepted { counter, .. } = outcome {
+                    entry.last_verified = Some(counter);
+                }
+                Ok(outcome)
+            })
+            .await?;
+        Ok(outcome)
+    }
+
+    /// Appends `entry` to the secrets file, with its secret normalised and leaving out the fields
+    /// that have their default value, returns its index. Fails when its handle is already used
+    pub async fn add_entry(&self, entry: &ConfigEntry) -> Result<usize> {
+        let defaults = serde_json::json!({"name": entry.name});
+        let defaults = serde_json::to_value(serde_json::from_value::<ConfigEntry>(defaults)?)?;
+        let mut entry = entry.clone();
+        entry.normalize();
+        let mut value = serde_json::to_value(&entry)?;
+        if let Some(fields) = value.as_object_mut() {
+            fields.retain(|key, field| key == "name" || defaults.get(key) != Some(field));
+        }
+        self.modify(|raw| {
+            check_handle(&parse_raw_entries(raw)?, &entry.handle, None)?;
+            raw.push(RawEntry {
+                origin: None,
+                value,
+            });
+            Ok(raw.len() - 1)
+        })
+        .await
+    }
+
+    /// Replaces the entry at `index`. Only the fields that differ are written, so that defaults
+    /// left out of the file stay out and fields unknown to this version are kept
+    pub async fn update_entry(&self, index: usize, entry: &ConfigEntry) -> Result<()> {
+        self.modify(|raw| {
+            let value = &mut raw.get_mut(index).ok_or(anyhow!("Entry not found"))?.value;
+            write_changes(value, entry)
+        })
+        .await
+    }
+
+    /// Applies `edit` to the entry `query` names exactly (see [`lookup::find_exact`]), found while
+    /// the file is locked so that a concurrent edit cannot make it another entry. Fails when the
+    /// edited handle is used by another entry. Returns the entry before and after the edit
+    pub async fn update_entry_by(
+        &self,
+        query: &str,
+        edit: impl FnOnce(&mut ConfigEntry),
+    ) -> Result<(ConfigEntry, ConfigEntry)> {
+        let find = |entries: &[ConfigEntry]| Ok(lookup::find_exact(entries, query)?);
+        let (_, after, before) = self
+            .edit_entry(find, |entry| {
+                let before = entry.clone();
+                edit(entry);
+                Ok(before)
+            })
+            .await?;
+        Ok((before, after))
+    }
+
+    /// Applies `edit` to the entry `find` picks among the entries as read under the lock, and
+    /// writes the fields that changed. Returns its index, the edited entry and what `edit` returned
+    async fn edit_entry<T>(
+        &self,
+        find: impl FnOnce(&[ConfigEntry]) -> Result<usize>,
+        edit: impl FnOnce(&mut ConfigEntry) -> Result<T>,
+    ) -> Result<(usize, ConfigEntry, T)> {
+        self.modify(|raw| {
+            let entries = parse_raw_entries(raw)?;
+            let index = find(&entries)?;
+            let mut entry = entries[index].clone();
+            // Secrets kept in other files are found relative to this one
+            entry.secret_cache = Some(Arc::clone(&self.secret_cache));
+            let result = edit(&mut entry)?;
+            check_handle(&entries, &entry.handle, Some(index))?;
+            write_changes(&mut raw[index].value, &entry)?;
+            Ok((index, entry, result))
+        })
+        .await
+    }
+
+    /// Removes the entry at `index` from the secrets file and returns it
+    pub async fn remove_entry(&self, index: usize) -> Result<ConfigEntry> {
+        let mut entry: ConfigEntry = self
+            .modify(|raw| {
+                if index >= raw.len() {
+                    bail!("Entry not found");
+                }
+                Ok(serde_json::from_value(raw.remove(index).value)?)
+            })
+            .await?;
+        entry.normalize();
+        Ok(entry)
+    }
+
+    /// Removes the entry `query` names exactly (see [`lookup::find_exact`]), found while the file
+    /// is locked, and returns it
+    pub async fn remove_entry_by(&self, query: &str) -> Result<ConfigEntry> {
+        self.modify(|raw| {
+            let mut entries = parse_raw_entries(raw)?;
+            let index = lookup::find_exact(&entries, query)?;
+            raw.remove(index);
+            Ok(entries.swap_remove(index))
+        })
+        .await
+    }
+
+    /// Moves the entry at `from` so that it ends up at index `to`, shifting those in between
+    pub async fn move_entry(&self, from: usize, to: usize) -> Result<()> {
+        self.modify(|raw| {
+            if from >= raw.len() || to >= raw.len() {
+                bail!("Entry not found");
+            }
+            let entry = raw.remove(from);
+            raw.insert(to, entry);
+            Ok(())
+        })
+        .await
+    }
+
+    /// Applies `edit` to the entries as written in the file and saves the result: under an
+    /// advisory lock so that other processes editing the file wait for their turn, in the same
+    /// format and encryption, after rotating the backups, and atomically. The entries in memory
+    /// are replaced by what was written, without reading the file again
+    async fn modify<T>(&self, edit: impl FnOnce(&mut Vec<RawEntry>) -> Result<T>) -> Result<T> {
+        let mut data = self.data.write().await;
+        let _lock = self.lock().await?;
+        let (content, encrypted) = self.read_plaintext().await?;
+        let mut raw = self.format.parse_raw(&content).with_context(|| {
+            format!("Failed to parse {} secrets from {}", self.format, self.secrets_path)
+        })?;
+        let before: Vec<serde_json::Value> = raw.iter().map(|entry| entry.value.clone()).collect();
+        let result = edit(&mut raw)?;
+        if raw.iter().map(|entry| &entry.value).eq(before.iter()) {
+            // Nothing to write, e.g. a code that was not accepted
+            return Ok(result);
+        }
+        let plaintext = Zeroizing::new(self.format.serialize(&raw, &content)?);
+        // Refuse to write something that could not be loaded afterwards
+        let entries = self.parse_entries(&plaintext)?;
+        let content = match (&self.passphrase, encrypted) {
+            (Some(passphrase), true) => encryption::encrypt(&plaintext, passphrase)?,
+            _ => plaintext.to_string(),
         };
-        entry.ok_or(anyhow!("Entry not found"))
+        self.rotate_backups().await?;
+        write_atomically(&self.secrets_path, content).await?;
+
+        let modified = fs::metadata(&self.secrets_path).await?.modified()?;
+        data.entries = entries;
+        data.last_modified = modified;
+        data.written = Some(modified);
+        drop(data);
+        self.changes.send_replace(Change::Edited);
+        Ok(result)
+    }
+
+    /// Takes the advisory lock shared with other processes editing the secrets file, released
+    /// when the returned file is dropped. A sibling file is locked since the secrets file itself
+    /// gets replaced
+    async fn lock(&self) -> Result<std::fs::File> {
+        let path = sibling_path(&self.secrets_path, |name| format!(".{name}.lock"))?;
+        let lock = tokio::task::spawn_blocking(move || -> Result<std::fs::File> {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(&path)
+                .with_context(|| format!("Failed to open {}", path.display()))?;
+            file.lock()
+                .with_context(|| format!("Failed to lock {}", path.display()))?;
+            Ok(file)
+        });
+        lock.await?
+    }
+
+    /// Shifts `<file>.1` to `<file>.2` and so on, dropping the oldest, then copies the file to
+    /// `<file>.1`. Copies keep the file's permissions and, if it is encrypted, its encryption
+    async fn rotate_backups(&self) -> Result<()> {
+        if self.backups == 0 {
+            return Ok(());
+        }
+        let backup =
+            |number: usize| sibling_path(&self.secrets_path, |name| format!("{name}.{number}"));
+        for number in (1..self.backups).rev() {
+            let older = backup(number)?;
+            if fs::try_exists(&older).await? {
+                fs::rename(&older, backup(number + 1)?).await?;
+            }
+        }
+        let latest = backup(1)?;
+        fs::copy(&self.secrets_path, &latest)
+            .await
+            .with_context(|| format!("Failed to back up to {}", latest.display()))?;
+        Ok(())
+    }
+
+    /// Finds an entry by `#index`, handle, name or part of it, see [`lookup::find`]
+    pub fn get_index(secrets: &[ConfigEntry], arg: &str) -> Result<usize> {
+        Ok(lookup::find(secrets, arg)?)
+    }
+
+    pub fn get_secret(secrets: &[ConfigEntry], arg: &str) -> Result<ConfigEntry> {
+        Self::get_index(secrets, arg).map(|index| secrets[index].clone())
+    }
+
+    /// Finds an entry by `#index`, handle or bare index only, see [`lookup::find_exact`]
+    pub fn get_exact_index(secrets: &[ConfigEntry], arg: &str) -> Result<usize> {
+        Ok(lookup::find_exact(secrets, arg)?)
+    }
+}
+
+/// Entries as written in the file, normalised like loaded ones
+fn parse_raw_entries(raw: &[RawEntry]) -> Result<Vec<ConfigEntry>> {
+    raw.iter()
+        .map(|raw| {
+            let mut entry: ConfigEntry = serde_json::from_value(raw.value.clone())?;
+            entry.normalize();
+            Ok(entry)
+        })
+        .collect()
+}
+
+/// Position in `entries`, as read under the lock, of the entry `loaded` at `index` in an earlier
+/// load. The file may have changed since, so the entry is recognised by its handle, or by its
+/// name when it has none, rather than trusted to still be at `index`
+fn find_loaded(entries: &[ConfigEntry], index: usize, loaded: &ConfigEntry) -> Result<usize> {
+    let same = |entry: &ConfigEntry| match loaded.handle.as_str() {
+        "" => entry.handle.is_empty() && entry.name == loaded.name,
+        handle => entry.handle == handle,
+    };
+    if entries.get(index).is_some_and(same) {
+        return Ok(index);
+    }
+    let mut matching = entries.iter().enumerate().filter(|(_, entry)| same(entry));
+    match (matching.next(), matching.next()) {
+        (Some((index, _)), None) => Ok(index),
+        (None, _) => bail!("{} is no longer in the secrets file", loaded.name),
+        (Some(_), Some(_)) => bail!(
+            "The secrets file changed and several entries are now named {}, try again",
+            loaded.name
+        ),
+    }
+}
+
+/// Fails when `handle` is already used by an entry other than `except`, since neither could be
+/// looked up by it anymore
+fn check_handle(entries: &[ConfigEntry], handle: &str, except: Option<usize>) -> Result<()> {
+    if handle.is_empty() {
+        return Ok(());
+    }
+    let used = entries
+        .iter()
+        .enumerate()
+        .find(|(index, entry)| Some(*index) != except && entry.handle == handle);
+    if let Some((index, entry)) = used {
+        bail!("Handle {handle} is already used by entry {index} ({})", entry.name);
+    }
+    Ok(())
+}
+
+/// Writes the fields of `entry` that differ from the entry `value` holds, and removes those it no
+/// longer has, so that defaults left out of the file stay out and unknown fields are kept
+fn write_changes(value: &mut serde_json::Value, entry: &ConfigEntry) -> Result<()> {
+    let after = serde_json::to_value(entry)?;
+    let mut previous: ConfigEntry = serde_json::from_value(value.clone())?;
+    previous.normalize();
+    let before = serde_json::to_value(&previous)?;
+    let (Some(fields), Some(before), Some(after)) =
+        (value.as_object_mut(), before.as_object(), after.as_object())
+    else {
+        bail!("Entry {} is not an object", entry.name);
+    };
+    for key in before.keys().filter(|key| !after.contains_key(*key)) {
+        fields.remove(key);
+    }
+    for (key, field) in after {
+        if before.get(key) != Some(field) {
+            fields.insert(key.clone(), field.clone());
+        }
+    }
+    Ok(())
+}
+
+/// File next to `path`, named after it by `name`
+fn sibling_path(path: &str, name: impl Fn(&str) -> String) -> Result<PathBuf> {
+    let target = Path::new(path);
+    let file_name = target
+        .file_name()
+        .ok_or(anyhow!("Invalid secrets path {}", path))?
+        .to_string_lossy();
+    Ok(target.with_file_name(name(&file_name)))
+}
+
+/// Writes to a sibling temporary file then renames it over `path`, so that readers never see a
+/// partially written secrets file
+async fn write_atomically(path: &str, content: String) -> Result<()> {
+    let target = Path::new(path);
+    let tmp = sibling_path(path, |name| format!(".{name}.tmp"))?;
+    let permissions = fs::metadata(target).await?.permissions();
+
+    let mut file = fs::File::create(&tmp)
+        .await
+        .with_context(|| format!("Failed to create {}", tmp.display()))?;
+    fs::set_permissions(&tmp, permissions).await?;
+    file.write_all(content.as_bytes()).await?;
+    file.sync_all().await?;
+    drop(file);
+
+    fs::rename(&tmp, target)
+        .await
+        .with_context(|| format!("Failed to replace {}", path))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_encrypted_file_stays_encrypted() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("secrets.json");
+        let passphrase = Secret::new("correct horse".to_owned());
+        let plaintext = r#"[{"name": "Test", "secret": "JBSWY3DPEHPK3PXP", "type": "hotp"}]"#;
+        fs::write(&path, encryption::encrypt(plaintext, &passphrase).unwrap())
+            .await
+            .unwrap();
+
+        let mut secrets_cf = ConfigFile::new(path.to_string_lossy().into_owned());
+        assert!(secrets_cf.load().await.is_err());
+        let wrong = Secret::new("wrong horse".to_owned());
+        assert!(!secrets_cf.unlock(&wrong).await.unwrap());
+        assert!(secrets_cf.load().await.is_err());
+        assert!(secrets_cf.unlock(&passphrase).await.unwrap());
+
+        // What was decrypted to unlock the file is not used once the file changed
+        let plaintext = plaintext.replace("Test", "Changed");
+        fs::write(&path, encryption::encrypt(&plaintext, &passphrase).unwrap())
+            .await
+            .unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+        let (_, entries) = secrets_cf.load().await.unwrap();
+        assert_eq!(entries[0].name, "Changed");
+        assert_eq!(entries[0].secret.expose(), "JBSWY3DPEHPK3PXP");
+
+        assert_eq!(secrets_cf.advance_counter(0, &entries[0]).await.unwrap().counter, 1);
+        let content = fs::read_to_string(&path).await.unwrap();
+        let backup = fs::read_to_string(path.with_extension("json.1")).await.unwrap();
+        assert!(encryption::is_encrypted(&content), "{content}");
+        assert!(encryption::is_encrypted(&backup), "{backup}");
+    }
+
+    #[tokio::test]
+    async fn test_advance_entry_moved_since_load() {
+        const SECRET: &str = "JBSWY3DPEHPK3PXP";
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("secrets.json");
+        let hotp =
+            |name: &str| format!(r#"{{"name": "{name}", "secret": "{SECRET}", "type": "hotp"}}"#);
+        fs::write(&path, format!("[{}, {}]", hotp("Bank"), hotp("Shop")))
+            .await
+            .unwrap();
+        let secrets_cf = ConfigFile::new(path.to_string_lossy().into_owned());
+        let (_, entries) = secrets_cf.load().await.unwrap();
+
+        // Another process inserts an entry before the one shown at index 1
+        let others = [hotp("Mail"), hotp("Bank"), hotp("Shop")].join(", ");
+        fs::write(&path, format!("[{others}]")).await.unwrap();
+        let advanced = secrets_cf.advance_counter(1, &entries[1]).await.unwrap();
+        assert_eq!((advanced.name.as_str(), advanced.counter), ("Shop", 1));
+        let counters: Vec<u64> = secrets_cf.entries().await.iter().map(|e| e.counter).collect();
+        assert_eq!(counters, [0, 0, 1]);
+
+        fs::write(&path, format!("[{}]", hotp("Mail"))).await.unwrap();
+        assert!(secrets_cf.advance_counter(1, &entries[1]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_created_file_is_private() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("secrets.yaml");
+        let secrets_cf = ConfigFile::new(path.to_string_lossy().into_owned());
+        assert!(secrets_cf.create().await.unwrap());
+        assert!(!secrets_cf.create().await.unwrap());
+        let entry = ConfigEntry::new("Mail".to_owned(), "JBSWY3DPEHPK3PXP".to_owned());
+        secrets_cf.add_entry(&entry).await.unwrap();
+        assert_eq!(secrets_cf.entries().await.len(), 1);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).await.unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600, "{mode:o}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_edit_entries() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("secrets.toml");
+        fs::write(
+            &path,
+            "[[entries]]\nname = \"Mail\" # personal\nsecret = \"jbsw y3dp ehpk 3pxp\"\n\
+             colour = \"red\"\n\n[[entries]]\nname = \"VPN\"\nsecret = \"GEZDGNBVGY3TQOJQ\"\n",
+        )
+        .await
+        .unwrap();
+        let secrets_cf = ConfigFile::new(path.to_string_lossy().into_owned()).with_backups(2);
+        let (_, entries) = secrets_cf.load().await.unwrap();
+
+        let mut added = entries[1].clone();
+        added.name = "Bank".to_owned();
+        assert_eq!(secrets_cf.add_entry(&added).await.unwrap(), 2);
+        let mut renamed = entries[0].clone();
+        renamed.name = "Email".to_owned();
+        renamed.tags = vec!["home".to_owned()];
+        secrets_cf.update_entry(0, &renamed).await.unwrap();
+        secrets_cf.move_entry(2, 0).await.unwrap();
+        assert_eq!(secrets_cf.remove_entry(2).await.unwrap().name, "VPN");
+
+        // Kept in memory without reading the file again
+        let (changed, entries) = secrets_cf.load().await.unwrap();
+        assert!(!changed);
+        let names: Vec<&str> = entries.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, ["Bank", "Email"]);
+
+        let written = fs::read_to_string(&path).await.unwrap();
+        assert!(written.contains("name = \"Email\" # personal"), "{written}");
+        assert!(written.contains("colour = \"red\""), "{written}");
+        assert!(written.contains("\"jbsw y3dp ehpk 3pxp\""), "{written}");
+        assert!(!written.contains("digits"), "{written}");
+        let backup = fs::read_to_string(path.with_extension("toml.1")).await.unwrap();
+        assert!(backup.contains("name = \"VPN\""), "{backup}");
+        assert!(fs::try_exists(path.with_extension("toml.2")).await.unwrap());
+        assert!(!fs::try_exists(path.with_extension("toml.3")).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_edit_entries_by_handle() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("secrets.json");
+        fs::write(
+            &path,
+            r#"[{"name": "GitHub", "handle": "gh", "secret": "JBSWY3DPEHPK3PXP"},
+                {"name": "Mail", "secret": "GEZDGNBVGY3TQOJQ"}]"#,
+        )
+        .await
+        .unwrap();
+        let secrets_cf = ConfigFile::new(path.to_string_lossy().into_owned()).with_backups(0);
+
+        // Only exact handles and indexes, never part of a name
+        assert!(secrets_cf.remove_entry_by("github").await.is_err());
+        let (before, after) = secrets_cf
+            .update_entry_by("#1", |entry| entry.handle = "mail".to_owned())
+            .await
+            .unwrap();
+        assert_eq!((before.handle.as_str(), after.handle.as_str()), ("", "mail"));
+        let renamed = secrets_cf
+            .update_entry_by("gh", |entry| entry.handle = "mail".to_owned())
+            .await;
+        assert!(renamed.is_err(), "no duplicate handles");
+        let mut added = after.clone();
+        added.name = "Other mail".to_owned();
+        assert!(secrets_cf.add_entry(&added).await.is_err(), "no duplicate handles");
+
+        assert_eq!(secrets_cf.remove_entry_by("gh").await.unwrap().name, "GitHub");
+        let (_, entries) = secrets_cf.load().await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].handle, "mail");
     }
 }
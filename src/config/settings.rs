@@ -0,0 +1,462 @@
+//! Settings file holding the options that would otherwise be repeated on every launch, e.g.
+//! `~/.config/totp-generator/config.toml`:
+//!
+//! ```toml
+//! clock_offset = -2
+//! passphrase_file = "~/.totp-passphrase"
+//!
+//! [interface]
+//! secrets = ["~/secrets.toml"]
+//! bind = "127.0.0.1"
+//! port = 8080
+//! number_style = "utf8"
+//! ```
+//!
+//! Keys are the long flags with underscores, of the program itself at the top (see `GLOBAL`) and
+//! of `interface` in its section. Values from the file become the defaults of those flags, so
+//! that flags and environment variables still override them. Paths may start with `~`, and
+//! relative ones are relative to the directory of the settings file
+
+use std::{
+    collections::BTreeMap,
+    env,
+    ffi::{OsStr, OsString},
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use clap::{ArgMatches, Command, CommandFactory, FromArgMatches, parser::ValueSource};
+
+use super::configuration::Args;
+
+/// Subcommand whose options can be set in the file
+const SECTION: &str = "interface";
+
+/// Options of the program itself that can be set at the top of the file
+const GLOBAL: &[&str] = &["log_file", "clock_offset", "passphrase_file", "secrets_format"];
+
+/// Values that are neither printed by `config show` nor shown as defaults by `--help`
+const HIDDEN: &[&str] = &["unlock_password"];
+
+/// Values that are paths, resolved against the settings file rather than the working directory
+const PATHS: &[&str] = &["secrets", "log_file", "passphrase_file"];
+
+type Values = BTreeMap<String, Vec<String>>;
+
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub path: PathBuf,
+    global: Values,
+    interface: Values,
+}
+
+/// Where the effective value of an option comes from, from highest to lowest precedence
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Origin {
+    CommandLine,
+    Environment(String),
+    SettingsFile(PathBuf),
+    Default,
+    Unset,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Origin::CommandLine => write!(f, "command line"),
+            Origin::Environment(name) => write!(f, "environment variable {name}"),
+            Origin::SettingsFile(path) => write!(f, "settings file {}", path.display()),
+            Origin::Default => write!(f, "default"),
+            Origin::Unset => write!(f, "unset"),
+        }
+    }
+}
+
+/// Effective value of an option of the program or of `interface`
+#[derive(Debug, Clone)]
+pub struct Effective {
+    pub key: String,
+    pub values: Vec<String>,
+    pub origin: Origin,
+}
+
+/// `$XDG_CONFIG_HOME/totp-generator/config.toml`, or under `~/.config` when it is not set
+pub fn default_path() -> Option<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config_home.join("totp-generator").join("config.toml"))
+}
+
+impl Settings {
+    /// Reads the settings file at `path`. A missing file is only an error when it was asked for
+    pub fn read(path: &Path, required: bool) -> Result<Option<Settings>> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound && !required => return Ok(None),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("Failed to read settings at {}", path.display()));
+            }
+        };
+        Settings::parse(path, &content).map(Some)
+    }
+
+    fn parse(path: &Path, content: &str) -> Result<Settings> {
+        let context = || format!("Invalid settings in {}", path.display());
+        let document: toml::Table = toml::from_str(content).with_context(context)?;
+        let directory = path.parent().unwrap_or(Path::new(""));
+        let mut global = BTreeMap::new();
+        let mut interface = BTreeMap::new();
+        for (key, value) in document {
+            match value {
+                toml::Value::Table(section) if key == SECTION => {
+                    for (key, value) in section {
+                        let values = parse_values(directory, &key, value)
+                            .with_context(|| format!("Invalid value for {SECTION}.{key}"))
+                            .with_context(context)?;
+                        interface.insert(key, values);
+                    }
+                }
+                toml::Value::Table(_) => {
+                    return Err(anyhow!("Unknown section {key}, expected [{SECTION}]"))
+                        .with_context(context);
+                }
+                value => {
+                    let values = parse_values(directory, &key, value)
+                        .with_context(|| format!("Invalid value for {key}"))
+                        .with_context(context)?;
+                    global.insert(key, values);
+                }
+            }
+        }
+        Ok(Settings {
+            path: path.to_owned(),
+            global,
+            interface,
+        })
+    }
+
+    /// Makes the values of the file the defaults of the options of the program and of `interface`
+    pub fn apply(&self, command: Command) -> Result<Command> {
+        let known = command
+            .find_subcommand(SECTION)
+            .map(option_ids)
+            .unwrap_or_default();
+        if let Some(key) = self.global.keys().find(|key| !GLOBAL.contains(&key.as_str())) {
+            bail!("Unknown setting {key} in {}", self.path.display());
+        }
+        if let Some(key) = self.interface.keys().find(|key| !known.contains(key)) {
+            bail!("Unknown setting {SECTION}.{key} in {}", self.path.display());
+        }
+        let command = set_defaults(command, &self.global);
+        Ok(command.mut_subcommand(SECTION, |subcommand| {
+            set_defaults(subcommand, &self.interface)
+        }))
+    }
+}
+
+/// Makes `values` the defaults of the options of `command`
+fn set_defaults(mut command: Command, values: &Values) -> Command {
+    for (key, values) in values {
+        // A value from the file satisfies a required option just as a flag would
+        command = command.mut_arg(key, |arg| {
+            arg.default_values(values.clone())
+                .required(false)
+                .hide_default_value(HIDDEN.contains(&key.as_str()))
+        });
+    }
+    command
+}
+
+fn parse_values(directory: &Path, key: &str, value: toml::Value) -> Result<Vec<String>> {
+    let values = match value {
+        toml::Value::Array(values) => values.iter().map(scalar).collect::<Result<Vec<_>>>()?,
+        value => vec![scalar(&value)?],
+    };
+    if !PATHS.contains(&key) {
+        return Ok(values);
+    }
+    Ok(values
+        .iter()
+        .map(|value| resolve_path(directory, value))
+        .collect())
+}
+
+/// Expands a leading `~` to the home directory, then makes `value` relative to `directory`
+fn resolve_path(directory: &Path, value: &str) -> String {
+    let home = env::var_os("HOME").filter(|home| !home.is_empty());
+    let expanded = match (value.strip_prefix('~'), home) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            PathBuf::from(home).join(rest.trim_start_matches('/'))
+        }
+        _ => PathBuf::from(value),
+    };
+    directory.join(expanded).to_string_lossy().into_owned()
+}
+
+fn scalar(value: &toml::Value) -> Result<String> {
+    match value {
+        toml::Value::String(value) => Ok(value.clone()),
+        toml::Value::Integer(value) => Ok(value.to_string()),
+        toml::Value::Float(value) => Ok(value.to_string()),
+        toml::Value::Boolean(value) => Ok(value.to_string()),
+        _ => bail!("expected a string, number, boolean or a list of them"),
+    }
+}
+
+fn option_ids(command: &Command) -> Vec<String> {
+    command
+        .get_arguments()
+        .filter(|arg| !arg.is_positional() && arg.get_id() != "help")
+        .map(|arg| arg.get_id().to_string())
+        .collect()
+}
+
+/// Path of the settings file given with `--config` or `TOTP_CONFIG`, found before the settings
+/// are applied to the flags
+fn requested_path(args: &[OsString]) -> Option<PathBuf> {
+    Args::command()
+        .ignore_errors(true)
+        .try_get_matches_from(args)
+        .ok()?
+        .get_one::<PathBuf>("config")
+        .cloned()
+}
+
+/// Reads the settings file, then parses the command line with the settings as defaults. Exits
+/// with the usual message on invalid arguments, like [`clap::Parser::parse`]
+pub fn parse_args() -> Result<(Args, Option<Settings>)> {
+    let args: Vec<OsString> = env::args_os().collect();
+    let settings = match requested_path(&args) {
+        Some(path) => Settings::read(&path, true)?,
+        None => match default_path() {
+            Some(path) => Settings::read(&path, false)?,
+            None => None,
+        },
+    };
+    let mut command = Args::command();
+    if let Some(settings) = &settings {
+        command = settings.apply(command)?;
+    }
+    let matches = command.get_matches_from(args);
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    split_env_paths(&matches, &mut args);
+    Ok((args, settings))
+}
+
+/// Splits secrets paths read from `TOTP_SECRETS` on the separator of `PATH`, which unlike a comma
+/// cannot be part of a path; flags give a single path each and are left alone
+fn split_env_paths(matches: &ArgMatches, args: &mut Args) {
+    let (Some(secrets), Some((_, matches))) = (args.mode.secrets_mut(), matches.subcommand())
+    else {
+        return;
+    };
+    if matches.value_source("secrets") == Some(ValueSource::EnvVariable) {
+        *secrets = split_paths(secrets.iter().map(OsStr::new));
+    }
+}
+
+fn split_paths<'a>(values: impl Iterator<Item = &'a OsStr>) -> Vec<String> {
+    values
+        .flat_map(env::split_paths)
+        .filter(|path| !path.as_os_str().is_empty())
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect()
+}
+
+/// Effective options of the program and of `interface` when launched without flags, and where
+/// they come from
+pub fn effective(settings: Option<&Settings>) -> Result<Vec<Effective>> {
+    let mut command = Args::command();
+    // Listed in the order of `--help`, which changing their defaults does not keep
+    let global_keys = option_ids(&command)
+        .into_iter()
+        .filter(|key| GLOBAL.contains(&key.as_str()))
+        .collect();
+    let keys = command
+        .find_subcommand(SECTION)
+        .map(option_ids)
+        .unwrap_or_default();
+    if let Some(settings) = settings {
+        command = settings.apply(command)?;
+    }
+    let interface = command
+        .find_subcommand(SECTION)
+        .cloned()
+        .ok_or(anyhow!("No {SECTION} subcommand"))?;
+    let in_file = |values: fn(&Settings) -> &Values| {
+        settings.map(|settings| (settings.path.as_path(), values(settings)))
+    };
+    let mut effective = effective_options(command, global_keys, in_file(|s| &s.global))?;
+    effective.extend(effective_options(interface, keys, in_file(|s| &s.interface))?);
+    Ok(effective)
+}
+
+/// Effective `keys` of `command`, `in_file` being the settings file and its values for them
+fn effective_options(
+    command: Command,
+    keys: Vec<String>,
+    in_file: Option<(&Path, &Values)>,
+) -> Result<Vec<Effective>> {
+    let env_names: BTreeMap<String, String> = command
+        .get_arguments()
+        .filter_map(|arg| {
+            let name = arg.get_env()?.to_string_lossy().into_owned();
+            Some((arg.get_id().to_string(), name))
+        })
+        .collect();
+    // Without ignoring errors, an option required but not set anywhere would fail here
+    let name = command.get_name().to_owned();
+    let matches = command.ignore_errors(true).try_get_matches_from([name])?;
+    Ok(keys
+        .into_iter()
+        .map(|key| {
+            let origin = origin(&matches, &key, in_file, &env_names);
+            let values = match matches.get_raw(&key) {
+                Some(_) if HIDDEN.contains(&key.as_str()) => vec!["********".to_owned()],
+                // As when parsing the command line, see split_env_paths
+                Some(values) if key == "secrets" && matches!(origin, Origin::Environment(_)) => {
+                    split_paths(values)
+                }
+                Some(values) => values
+                    .map(|value| value.to_string_lossy().into_owned())
+                    .collect(),
+                None => Vec::new(),
+            };
+            Effective {
+                key,
+                values,
+                origin,
+            }
+        })
+        .collect())
+}
+
+fn origin(
+    matches: &ArgMatches,
+    key: &str,
+    in_file: Option<(&Path, &Values)>,
+    env_names: &BTreeMap<String, String>,
+) -> Origin {
+    match matches.value_source(key) {
+        Some(ValueSource::CommandLine) => Origin::CommandLine,
+        Some(ValueSource::EnvVariable) => {
+            Origin::Environment(env_names.get(key).cloned().unwrap_or_default())
+        }
+        Some(ValueSource::DefaultValue) => match in_file {
+            Some((path, values)) if values.contains_key(key) => {
+                Origin::SettingsFile(path.to_owned())
+            }
+            _ => Origin::Default,
+        },
+        _ => Origin::Unset,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::format::SecretsFormat;
+
+    #[test]
+    fn test_settings() {
+        let path = Path::new("config.toml");
+        let settings = Settings::parse(
+            path,
+            r#"
+[interface]
+secrets = ["a.json", "b.toml"]
+port = 8080
+no_console = true
+"#,
+        )
+        .unwrap();
+        let command = settings.apply(Args::command()).unwrap();
+        let matches = command
+            .try_get_matches_from(["totp-generator", "interface", "--port", "9000"])
+            .unwrap();
+        let args = Args::from_arg_matches(&matches).unwrap();
+        let crate::config::configuration::Mode::Interface {
+            secrets,
+            port,
+            no_console,
+            lock_after,
+            ..
+        } = args.mode
+        else {
+            panic!("Not the interface");
+        };
+        assert_eq!(secrets, ["a.json", "b.toml"]);
+        assert_eq!((port, no_console, lock_after), (9000, true, 300));
+
+        let effective = effective(Some(&settings)).unwrap();
+        let origin = |key: &str| {
+            let value = effective.iter().find(|value| value.key == key).unwrap();
+            (value.values.join(","), value.origin.clone())
+        };
+        let file = Origin::SettingsFile(path.to_owned());
+        assert_eq!(origin("port"), ("8080".to_owned(), file.clone()));
+        assert_eq!(origin("lock_after"), ("300".to_owned(), Origin::Default));
+        assert_eq!(origin("bind"), (String::new(), Origin::Unset));
+
+        // Paths are relative to the settings file, not to where the program is launched from
+        let home = PathBuf::from(env::var_os("HOME").unwrap());
+        let nested = Settings::parse(
+            Path::new("/etc/totp/config.toml"),
+            "[interface]\nsecrets = [\"~/mine.toml\", \"team/\", \"/srv/shared.json\"]",
+        )
+        .unwrap();
+        assert_eq!(
+            nested.interface["secrets"],
+            [
+                home.join("mine.toml").to_string_lossy(),
+                "/etc/totp/team/".into(),
+                "/srv/shared.json".into()
+            ]
+        );
+
+        let unknown = Settings::parse(path, "[interface]\nprot = 1").unwrap();
+        assert!(unknown.apply(Args::command()).is_err());
+        let unknown = Settings::parse(path, "std_err = true").unwrap();
+        assert!(unknown.apply(Args::command()).is_err());
+        assert!(Settings::parse(path, "[ui]\nport = 1").is_err());
+    }
+
+    #[test]
+    fn test_global_settings() {
+        let path = Path::new("/etc/totp/config.toml");
+        let settings = Settings::parse(
+            path,
+            r#"
+clock_offset = -2
+passphrase_file = "passphrase"
+secrets_format = "toml"
+
+[interface]
+secrets = ["a.json"]
+"#,
+        )
+        .unwrap();
+        let command = settings.apply(Args::command()).unwrap();
+        let matches = command
+            .try_get_matches_from(["totp-generator", "--clock-offset", "5", "interface"])
+            .unwrap();
+        let args = Args::from_arg_matches(&matches).unwrap();
+        assert_eq!(args.clock_offset, 5);
+        assert_eq!(args.passphrase_file, Some("/etc/totp/passphrase".into()));
+        assert_eq!(args.secrets_format, Some(SecretsFormat::Toml));
+
+        let effective = effective(Some(&settings)).unwrap();
+        let keys: Vec<&str> = effective.iter().map(|value| value.key.as_str()).collect();
+        assert_eq!(
+            keys[..4],
+            ["log_file", "clock_offset", "passphrase_file", "secrets_format"]
+        );
+        let file = Origin::SettingsFile(path.to_owned());
+        assert_eq!(effective[0].origin, Origin::Unset);
+        assert_eq!((effective[1].values.join(","), &effective[1].origin), ("-2".into(), &file));
+    }
+}
@@ -0,0 +1,279 @@
+//! Several secrets files, or directories of them, merged into a single list of entries
+
+use std::{collections::HashSet, path::Path, sync::Arc};
+
+use anyhow::{Context, Result, anyhow, bail};
+use tokio::sync::watch;
+
+use super::secrets::{Change, ConfigEntry, ConfigFile};
+use crate::verify::{Verification, Verifier};
+
+/// Separates the source prefix from the entry's own handle, e.g. `team:gmail`
+pub const PREFIX_SEPARATOR: char = ':';
+
+const EXTENSIONS: [&str; 4] = ["json", "toml", "yaml", "yml"];
+
+#[derive(Debug)]
+struct Source {
+    /// Only set when there is more than one source, so that a single file keeps its handles
+    prefix: Option<String>,
+    file: Arc<ConfigFile>,
+}
+
+/// Secrets files read as one: entries are listed file after file, each source is loaded (and
+/// reloaded) on its own, and with several sources handles are prefixed with the file stem
+#[derive(Debug)]
+pub struct ConfigSources {
+    sources: Vec<Source>,
+}
+
+/// Result of loading every source; a source that fails keeps the entries it last loaded
+pub struct Loaded {
+    pub changed: bool,
+    pub entries: Vec<ConfigEntry>,
+    pub errors: Vec<anyhow::Error>,
+}
+
+impl ConfigSources {
+    pub fn new(files: Vec<ConfigFile>) -> Result<Self> {
+        if files.is_empty() {
+            bail!("No secrets file given");
+        }
+        let prefixed = files.len() > 1;
+        let mut prefixes = HashSet::new();
+        let mut sources = Vec::with_capacity(files.len());
+        for file in files {
+            let prefix = if prefixed {
+                let prefix = source_prefix(&file.secrets_path)?;
+                if !prefixes.insert(prefix.clone()) {
+                    bail!("Several secrets files are named {prefix}, their handles would clash");
+                }
+                Some(prefix)
+            } else {
+                None
+            };
+            sources.push(Source {
+                prefix,
+                file: Arc::new(file),
+            });
+        }
+        Ok(ConfigSources { sources })
+    }
+
+    pub fn single(file: ConfigFile) -> Self {
+        ConfigSources {
+            sources: vec![Source {
+                prefix: None,
+                file: Arc::new(file),
+            }],
+        }
+    }
+
+    /// Replaces directories by the secrets files they contain (by extension, sorted by name)
+    pub fn expand_paths(paths: &[String]) -> Result<Vec<String>> {
+        let mut expanded = Vec::new();
+        for path in paths {
+            if !Path::new(path).is_dir() {
+                expanded.push(path.clone());
+                continue;
+            }
+            let mut files: Vec<String> = std::fs::read_dir(path)
+                .with_context(|| format!("Failed to list secrets directory {path}"))?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|file| file.is_file() && is_secrets_file(file))
+                .map(|file| file.to_string_lossy().into_owned())
+                .collect();
+            if files.is_empty() {
+                bail!("No secrets file found in {path}");
+            }
+            files.sort();
+            expanded.extend(files);
+        }
+        Ok(expanded)
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = &Arc<ConfigFile>> {
+        self.sources.iter().map(|source| &source.file)
+    }
+
+    /// Paths of all sources, for messages
+    pub fn describe(&self) -> String {
+        self.files()
+            .map(|file| file.secrets_path.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Loads every source, see [`ConfigFile::load`]
+    pub async fn load_all(&self) -> Loaded {
+        let mut loaded = Loaded {
+            changed: false,
+            entries: Vec::new(),
+            errors: Vec::new(),
+        };
+        for source in &self.sources {
+            let entries = match source.file.load().await {
+                Ok((changed, entries)) => {
+                    loaded.changed |= changed;
+                    entries
+                }
+                Err(err) => {
+                    loaded.errors.push(err);
+                    source.file.entries().await
+                }
+            };
+            loaded
+                .entries
+                .extend(entries.into_iter().map(|entry| source.tag(entry)));
+        }
+        loaded
+    }
+
+    /// Returns the merged entries and whether any source was reloaded. Sources that fail to load
+    /// are logged and keep their previous entries; it is only an error when none could be loaded
+    pub async fn load(&self) -> Result<(bool, Vec<ConfigEntry>)> {
+        let Loaded {
+            changed,
+            entries,
+            mut errors,
+        } = self.load_all().await;
+        if errors.len() == self.sources.len() {
+            return Err(errors.remove(0));
+        }
+        for err in errors {
+            tracing::warn!("Failed to load secrets: {err:#}");
+        }
+        Ok((changed, entries))
+    }
+
+    /// Advances the counter of `entry`, loaded at `index` of the merged list, in the file it
+    /// comes from; see [`ConfigFile::advance_counter`]
+    pub async fn advance_counter(&self, index: usize, entry: &ConfigEntry) -> Result<ConfigEntry> {
+        let (source, index, entry) = self.locate(index, entry).await?;
+        let advanced = source.file.advance_counter(index, &entry).await?;
+        Ok(source.tag(advanced))
+    }
+
+    /// Verifies `code` against `entry`, loaded at `index` of the merged list, see
+    /// [`ConfigFile::verify`]
+    pub async fn verify(
+        &self,
+        index: usize,
+        entry: &ConfigEntry,
+        code: &str,
+        verifier: &Verifier,
+    ) -> Result<Verification> {
+        let (source, index, entry) = self.locate(index, entry).await?;
+        source.file.verify(index, &entry, code, verifier).await
+    }
+
+    /// The source `entry` was loaded from, with its position in that source's file and the entry
+    /// as written there
+    async fn locate(
+        &self,
+        index: usize,
+        entry: &ConfigEntry,
+    ) -> Result<(&Source, usize, ConfigEntry)> {
+        let mut offset = 0;
+        for source in &self.sources {
+            if entry.source == source.prefix {
+                return Ok((source, index.saturating_sub(offset), source.untag(entry)));
+            }
+            offset += source.file.entries().await.len();
+        }
+        Err(anyhow!("Entry not found"))
+    }
+
+    /// One receiver per source, see [`ConfigFile::subscribe`]
+    pub fn subscribe(&self) -> Vec<watch::Receiver<Change>> {
+        self.files().map(|file| file.subscribe()).collect()
+    }
+
+    pub fn is_watched(&self) -> bool {
+        self.files().all(|file| file.is_watched())
+    }
+}
+
+impl Source {
+    fn tag(&self, mut entry: ConfigEntry) -> ConfigEntry {
+        if let Some(prefix) = &self.prefix {
+            if !entry.handle.is_empty() {
+                entry.handle = format!("{prefix}{PREFIX_SEPARATOR}{}", entry.handle);
+            }
+            entry.source = Some(prefix.clone());
+        }
+        entry
+    }
+
+    /// The entry as it is written in this source's file
+    fn untag(&self, entry: &ConfigEntry) -> ConfigEntry {
+        let mut entry = entry.clone();
+        if let Some(prefix) = &self.prefix
+            && let Some(handle) = entry.handle.strip_prefix(&format!("{prefix}{PREFIX_SEPARATOR}"))
+        {
+            entry.handle = handle.to_string();
+        }
+        entry.source = None;
+        entry
+    }
+}
+
+fn source_prefix(path: &str) -> Result<String> {
+    Path::new(path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .ok_or(anyhow!("Invalid secrets path {path}"))
+}
+
+fn is_secrets_file(path: &Path) -> bool {
+    let hidden = path
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+    !hidden && extension.is_some_and(|extension| EXTENSIONS.contains(&extension.as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_merged_sources() {
+        let directory = tempfile::tempdir().unwrap();
+        let directory = directory.path();
+        std::fs::write(
+            directory.join("team.json"),
+            r#"[{"name": "Shared", "handle": "vpn", "secret": "JBSWY3DPEHPK3PXP"}]"#,
+        )
+        .unwrap();
+        std::fs::write(
+            directory.join("personal.toml"),
+            "[[entries]]\nname = \"Mine\"\nhandle = \"mail\"\nsecret = \"JBSWY3DPEHPK3PXP\"\n\
+             type = \"hotp\"\n",
+        )
+        .unwrap();
+        std::fs::write(directory.join("notes.txt"), "not secrets").unwrap();
+
+        let paths = ConfigSources::expand_paths(&[directory.to_string_lossy().into_owned()]);
+        let files = paths.unwrap().into_iter().map(ConfigFile::new).collect();
+        let sources = ConfigSources::new(files).unwrap();
+        let (changed, entries) = sources.load().await.unwrap();
+        assert!(changed);
+        let handles: Vec<&str> = entries.iter().map(|entry| entry.handle.as_str()).collect();
+        assert_eq!(handles, ["personal:mail", "team:vpn"]);
+        assert_eq!(entries[1].source.as_deref(), Some("team"));
+
+        // The counter is written to the file the entry comes from, without the prefix
+        let entry = sources.advance_counter(0, &entries[0]).await.unwrap();
+        assert_eq!((entry.handle.as_str(), entry.counter), ("personal:mail", 1));
+        let written = std::fs::read_to_string(directory.join("personal.toml")).unwrap();
+        assert!(written.contains("handle = \"mail\""), "{written}");
+
+        // A broken source does not hide the others
+        std::fs::write(directory.join("team.json"), "oops").unwrap();
+        let (_, entries) = sources.load().await.unwrap();
+        assert_eq!(entries.len(), 2);
+    }
+}
@@ -0,0 +1,128 @@
+//! Reloads the secrets file when the file system reports a change, rather than polling it
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::{
+    sync::mpsc::{self, UnboundedReceiver},
+    task::JoinHandle,
+    time,
+};
+
+use super::secrets::ConfigFile;
+
+/// Watches the secrets file for as long as it is kept alive
+pub struct SecretsWatcher {
+    _watcher: RecommendedWatcher,
+    task: JoinHandle<()>,
+    secrets_cf: Arc<ConfigFile>,
+}
+
+impl Drop for SecretsWatcher {
+    fn drop(&mut self) {
+        self.task.abort();
+        self.secrets_cf.set_watched(false);
+    }
+}
+
+/// Starts reloading `secrets_cf` once its file has been quiet for `debounce` after a change.
+///
+/// The parent directory is watched rather than the file itself: editors that save by writing a
+/// new file and renaming it over the old one would otherwise leave us watching a deleted inode.
+pub fn watch(secrets_cf: Arc<ConfigFile>, debounce: Duration) -> Result<SecretsWatcher> {
+    let path = Path::new(&secrets_cf.secrets_path);
+    let file_name = path
+        .file_name()
+        .with_context(|| format!("Invalid secrets path {}", secrets_cf.secrets_path))?
+        .to_owned();
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+
+    let (tx, rx) = mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        match event {
+            Ok(event) if is_relevant(&event, &file_name) => {
+                let _ = tx.send(());
+            }
+            Ok(_) => {}
+            Err(err) => tracing::warn!("Error watching secrets file: {err}"),
+        }
+    })?;
+    watcher
+        .watch(&directory, RecursiveMode::NonRecursive)
+        .with_context(|| format!("Failed to watch {}", directory.display()))?;
+    tracing::info!("Watching {} for changes", secrets_cf.secrets_path);
+
+    secrets_cf.set_watched(true);
+    let task = tokio::spawn(reload_on_change(Arc::clone(&secrets_cf), rx, debounce));
+    Ok(SecretsWatcher {
+        _watcher: watcher,
+        task,
+        secrets_cf,
+    })
+}
+
+fn is_relevant(event: &Event, file_name: &std::ffi::OsStr) -> bool {
+    !matches!(event.kind, EventKind::Access(_))
+        && event
+            .paths
+            .iter()
+            .any(|path| path.file_name() == Some(file_name))
+}
+
+async fn reload_on_change(
+    secrets_cf: Arc<ConfigFile>,
+    mut rx: UnboundedReceiver<()>,
+    debounce: Duration,
+) {
+    while rx.recv().await.is_some() {
+        // Saving usually comes as a burst of events (truncate, writes, rename...), wait for it to
+        // settle so that a half written file is not picked up
+        while let Ok(Some(())) = time::timeout(debounce, rx.recv()).await {}
+        match secrets_cf.reload().await {
+            Ok(true) => tracing::info!("Secrets file {} reloaded", secrets_cf.secrets_path),
+            Ok(false) => {}
+            Err(err) => tracing::warn!("Failed to reload {}: {err}", secrets_cf.secrets_path),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_reloads_on_atomic_replace() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("secrets.json");
+        std::fs::write(&path, r#"[{"name": "Before", "secret": "JBSWY3DPEHPK3PXP"}]"#).unwrap();
+
+        let secrets_cf = Arc::new(ConfigFile::new(path.to_string_lossy().into_owned()));
+        let watcher = watch(Arc::clone(&secrets_cf), Duration::from_millis(50)).unwrap();
+        let mut changes = secrets_cf.subscribe();
+        assert_eq!(secrets_cf.load().await.unwrap().1[0].name, "Before");
+
+        // Written elsewhere then renamed over, as many editors do
+        let tmp = directory.path().join("secrets.json.new");
+        std::fs::write(&tmp, r#"[{"name": "After", "secret": "JBSWY3DPEHPK3PXP"}]"#).unwrap();
+        std::fs::rename(&tmp, &path).unwrap();
+
+        time::timeout(Duration::from_secs(5), changes.changed())
+            .await
+            .unwrap()
+            .unwrap();
+        let (changed, entries) = secrets_cf.load().await.unwrap();
+        assert!(!changed);
+        assert_eq!(entries[0].name, "After");
+
+        drop(watcher);
+        assert!(!secrets_cf.is_watched());
+    }
+}
@@ -0,0 +1,186 @@
+//! Schemes turning an entry's secret into tokens, looked up by the entry's `type`
+//!
+//! Every entry type maps to an [`OtpGenerator`] in a process wide registry. The RFC 6238 (TOTP),
+//! RFC 4226 (HOTP), Steam Guard and mOTP generators are registered by default; other schemes can
+//! be added with [`register`] and are then picked up by the console UI, one-time mode and the
+//! HTTP API alike.
+
+mod motp;
+mod rfc;
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock, RwLock},
+};
+
+pub use motp::Motp;
+pub use rfc::{Rfc4226, Rfc6238, SteamGuard};
+
+use crate::{
+    clock::Clock,
+    config::secrets::ConfigEntry,
+    secret::Secret,
+    totp::{self, OtpType, TimeStep, Totp, TotpError},
+};
+
+/// A way of deriving tokens from an entry and a moving factor
+pub trait OtpGenerator: Send + Sync {
+    /// How time is divided into tokens, or `None` when the moving factor is the entry's stored
+    /// counter
+    fn time_step(&self, entry: &ConfigEntry) -> Option<TimeStep>;
+
+    /// Token for a given value of the moving factor
+    fn token(&self, entry: &ConfigEntry, key: &[u8], counter: u64) -> Result<String, TotpError>;
+
+    /// Key material tokens are derived from
+    fn key(&self, entry: &ConfigEntry) -> Result<Secret<Vec<u8>>, TotpError> {
+        totp::decode_secret(entry.secret()?.expose(), entry.encoding)
+    }
+
+    /// Checks the entry's parameters; the secret itself is checked by [`OtpGenerator::key`]
+    fn validate(&self, entry: &ConfigEntry) -> Result<(), TotpError> {
+        if self
+            .time_step(entry)
+            .is_some_and(|time_step| time_step.seconds == 0)
+        {
+            return Err(TotpError::ZeroTimestep);
+        }
+        Ok(())
+    }
+}
+
+type Registry = HashMap<OtpType, Arc<dyn OtpGenerator>>;
+
+static REGISTRY: LazyLock<RwLock<Registry>> = LazyLock::new(|| {
+    RwLock::new(HashMap::from([
+        (OtpType::Totp, Arc::new(Rfc6238) as Arc<dyn OtpGenerator>),
+        (OtpType::Hotp, Arc::new(Rfc4226)),
+        (OtpType::Steam, Arc::new(SteamGuard)),
+        (OtpType::Motp, Arc::new(Motp)),
+    ]))
+});
+
+/// Makes entries of `otp_type` use `generator`, replacing any generator registered before
+pub fn register(otp_type: OtpType, generator: Arc<dyn OtpGenerator>) {
+    REGISTRY.write().unwrap().insert(otp_type, generator);
+}
+
+/// Generator registered for `otp_type`
+pub fn lookup(otp_type: &OtpType) -> Result<Arc<dyn OtpGenerator>, TotpError> {
+    REGISTRY
+        .read()
+        .unwrap()
+        .get(otp_type)
+        .cloned()
+        .ok_or_else(|| TotpError::UnknownType(otp_type.to_string()))
+}
+
+/// Current token of the entry: time based generators use the clock, the others the stored
+/// counter
+pub fn generate(entry: &ConfigEntry, clock: &dyn Clock) -> Result<Totp, TotpError> {
+    let key = lookup(&entry.otp_type)?.key(entry)?;
+    generate_with_key(entry, &key, clock)
+}
+
+/// Same as [`generate`] with a key decoded beforehand, so that callers refreshing the same entry
+/// over and over do not decode its secret every time
+pub fn generate_with_key(
+    entry: &ConfigEntry,
+    key: &Secret<Vec<u8>>,
+    clock: &dyn Clock,
+) -> Result<Totp, TotpError> {
+    let generator = lookup(&entry.otp_type)?;
+    generator.validate(entry)?;
+    let key = key.expose();
+    match generator.time_step(entry) {
+        Some(time_step) => {
+            let counter = time_step.counter(clock.unix_timestamp());
+            let token = generator.token(entry, key, counter)?;
+            let next_token = generator.token(entry, key, counter + 1)?;
+            Ok(Totp::from_parts(
+                token,
+                Some(next_token),
+                counter,
+                time_step.end(counter),
+            ))
+        }
+        None => {
+            let token = generator.token(entry, key, entry.counter)?;
+            Ok(Totp::from_parts(token, None, entry.counter, 0))
+        }
+    }
+}
+
+/// Checks `code` against every time step within `skew` steps of the one at `timestamp` and
+/// returns the counter it matched; entries that are not time based never match
+pub fn verify(
+    entry: &ConfigEntry,
+    code: &str,
+    skew: u8,
+    timestamp: u64,
+) -> Result<Option<u64>, TotpError> {
+    let generator = lookup(&entry.otp_type)?;
+    generator.validate(entry)?;
+    let Some(time_step) = generator.time_step(entry) else {
+        return Ok(None);
+    };
+    let key = generator.key(entry)?;
+    let key = key.expose();
+    let counter = time_step.counter(timestamp);
+    let skew = skew as u64;
+    for candidate in counter.saturating_sub(skew)..=counter.saturating_add(skew) {
+        let expected = generator.token(entry, key, candidate)?;
+        if totp::constant_time_eq(expected.as_bytes(), code.as_bytes()) {
+            return Ok(Some(candidate));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::FakeClock;
+
+    /// Spells the counter backwards, which is enough to tell it apart from the built-in schemes
+    struct Reversed;
+
+    impl OtpGenerator for Reversed {
+        fn time_step(&self, _entry: &ConfigEntry) -> Option<TimeStep> {
+            Some(TimeStep::seconds(60))
+        }
+
+        fn token(
+            &self,
+            _entry: &ConfigEntry,
+            _key: &[u8],
+            counter: u64,
+        ) -> Result<String, TotpError> {
+            Ok(counter.to_string().chars().rev().collect())
+        }
+    }
+
+    #[test]
+    fn test_registered_generator() {
+        let entry: ConfigEntry = serde_json::from_str(
+            r#"{"name": "Test", "secret": "JBSWY3DPEHPK3PXP", "type": "reversed"}"#,
+        )
+        .unwrap();
+        assert_eq!(entry.otp_type, OtpType::Other("reversed".to_owned()));
+        let clock = FakeClock::at(1748742637);
+        assert_eq!(
+            generate(&entry, &clock).unwrap_err(),
+            TotpError::UnknownType("reversed".to_owned())
+        );
+
+        register(entry.otp_type.clone(), Arc::new(Reversed));
+        let totp = generate(&entry, &clock).unwrap();
+        assert_eq!(totp.token, "01754192");
+        assert_eq!(totp.next_token.as_deref(), Some("11754192"));
+        assert_eq!(totp.valid_until, 1748742660);
+        assert_eq!(
+            verify(&entry, "11754192", 1, 1748742637),
+            Ok(Some(29145711))
+        );
+    }
+}
@@ -0,0 +1,83 @@
+use md5::{Digest, Md5};
+
+use super::OtpGenerator;
+use crate::{
+    config::secrets::ConfigEntry,
+    secret::Secret,
+    totp::{SecretEncoding, TimeStep, TotpError},
+};
+
+/// Mobile-OTP: the first 6 hex digits of MD5 over the time in tens of seconds, the secret and the
+/// PIN, all as text
+#[derive(Debug)]
+pub struct Motp;
+
+impl OtpGenerator for Motp {
+    fn time_step(&self, _entry: &ConfigEntry) -> Option<TimeStep> {
+        Some(TimeStep::seconds(10))
+    }
+
+    fn token(&self, entry: &ConfigEntry, key: &[u8], counter: u64) -> Result<String, TotpError> {
+        let pin = entry.pin.as_ref().ok_or(TotpError::MissingPin)?;
+        let mut hasher = Md5::new();
+        hasher.update(counter.to_string());
+        hasher.update(key);
+        hasher.update(pin.expose());
+        let hex: String = hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        Ok(hex[..6].to_owned())
+    }
+
+    /// The secret is hashed as written, usually 16 lowercase hex characters; it is lowercased
+    /// since loading the secrets file uppercases base32 looking secrets
+    fn key(&self, entry: &ConfigEntry) -> Result<Secret<Vec<u8>>, TotpError> {
+        let mut secret: String = entry.secret()?.expose().split_whitespace().collect();
+        secret.make_ascii_lowercase();
+        let secret = Secret::new(secret.into_bytes());
+        let bytes = secret.expose();
+        if bytes.is_empty() || !bytes.iter().all(u8::is_ascii_hexdigit) {
+            return Err(TotpError::MalformedSecret(SecretEncoding::Hex));
+        }
+        Ok(secret)
+    }
+
+    fn validate(&self, entry: &ConfigEntry) -> Result<(), TotpError> {
+        if entry.pin.as_ref().is_none_or(|pin| pin.expose().is_empty()) {
+            return Err(TotpError::MissingPin);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{clock::FakeClock, generator};
+
+    use super::*;
+
+    #[test]
+    fn test_motp_tokens() {
+        let mut entry: ConfigEntry = serde_json::from_str(
+            r#"{"name": "Test", "secret": "0123456789abcdef", "type": "motp"}"#,
+        )
+        .unwrap();
+        let clock = FakeClock::at(1748742637);
+        assert_eq!(
+            generator::generate(&entry, &clock).unwrap_err(),
+            TotpError::MissingPin
+        );
+
+        entry.pin = Some(Secret::new("1234".to_owned()));
+        let totp = generator::generate(&entry, &clock).unwrap();
+        assert_eq!(totp.token, "dca41e");
+        assert_eq!(totp.valid_until, 1748742640);
+
+        // Secrets are uppercased when the file is loaded
+        entry.secret = Secret::new("0123 4567 89AB CDEF".to_owned());
+        clock.advance(std::time::Duration::from_secs(26));
+        assert_eq!(generator::generate(&entry, &clock).unwrap().token, "e4895a");
+    }
+}
@@ -0,0 +1,66 @@
+use super::OtpGenerator;
+use crate::{
+    config::secrets::ConfigEntry,
+    totp::{self, TimeStep, TokenFormat, TotpError},
+};
+
+/// Time based one-time passwords (RFC 6238)
+#[derive(Debug)]
+pub struct Rfc6238;
+
+impl OtpGenerator for Rfc6238 {
+    fn time_step(&self, entry: &ConfigEntry) -> Option<TimeStep> {
+        Some(entry.time_step())
+    }
+
+    fn token(&self, entry: &ConfigEntry, key: &[u8], counter: u64) -> Result<String, TotpError> {
+        totp::generate_hotp(
+            key,
+            counter,
+            TokenFormat::Decimal(entry.digits),
+            entry.algorithm,
+        )
+    }
+
+    fn validate(&self, entry: &ConfigEntry) -> Result<(), TotpError> {
+        totp::validate_parameters(entry.time_step(), TokenFormat::Decimal(entry.digits))
+    }
+}
+
+/// Counter based one-time passwords (RFC 4226); the counter is stored in the entry
+#[derive(Debug)]
+pub struct Rfc4226;
+
+impl OtpGenerator for Rfc4226 {
+    fn time_step(&self, _entry: &ConfigEntry) -> Option<TimeStep> {
+        None
+    }
+
+    fn token(&self, entry: &ConfigEntry, key: &[u8], counter: u64) -> Result<String, TotpError> {
+        totp::generate_hotp(
+            key,
+            counter,
+            TokenFormat::Decimal(entry.digits),
+            entry.algorithm,
+        )
+    }
+
+    fn validate(&self, entry: &ConfigEntry) -> Result<(), TotpError> {
+        // The time step is not used, only the digits matter
+        totp::validate_parameters(TimeStep::seconds(1), TokenFormat::Decimal(entry.digits))
+    }
+}
+
+/// Steam Guard: the RFC 6238 moving factor, spelt with 5 letters; `digits` is ignored
+#[derive(Debug)]
+pub struct SteamGuard;
+
+impl OtpGenerator for SteamGuard {
+    fn time_step(&self, entry: &ConfigEntry) -> Option<TimeStep> {
+        Some(entry.time_step())
+    }
+
+    fn token(&self, entry: &ConfigEntry, key: &[u8], counter: u64) -> Result<String, TotpError> {
+        totp::generate_hotp(key, counter, TokenFormat::Steam, entry.algorithm)
+    }
+}
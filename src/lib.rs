@@ -0,0 +1,52 @@
+//! Generate and verify one-time passwords (RFC 6238 TOTP and RFC 4226 HOTP).
+//!
+//! The binary is a thin layer over this crate; other programs can use the same pieces: token
+//! generation with [`Totp`], the secrets file with [`ConfigFile`] and [`ConfigEntry`], checking
+//! codes without accepting one twice with [`Verifier`], and, with the `configure` feature, Google
+//! Authenticator export parsing with `QrDecoder`. Schemes beyond the built-in ones can be plugged
+//! in by implementing [`OtpGenerator`] and registering it with [`generator::register`].
+//!
+//! ```
+//! use totp_generator::{ConfigEntry, clock::SystemClock};
+//!
+//! let entry: ConfigEntry =
+//!     serde_json::from_str(r#"{"name": "Example", "secret": "JBSWY3DPEHPK3PXP"}"#)?;
+//! let totp = entry.totp(&SystemClock)?;
+//! assert_eq!(totp.token.len(), 6);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+pub mod clock;
+pub mod generator;
+pub mod secret;
+pub mod totp;
+
+// Plumbing of the binary, which is a crate of its own and so can only reach public items; not
+// part of the API and free to change between versions
+#[doc(hidden)]
+pub mod check;
+#[doc(hidden)]
+pub mod config;
+#[doc(hidden)]
+pub mod manage;
+#[doc(hidden)]
+pub mod output;
+#[doc(hidden)]
+pub mod qr;
+#[doc(hidden)]
+pub mod skew;
+#[doc(hidden)]
+pub mod state;
+#[doc(hidden)]
+pub mod verify;
+
+pub use config::{
+    format::SecretsFormat,
+    secrets::{ConfigEntry, ConfigFile},
+    sources::ConfigSources,
+};
+pub use generator::OtpGenerator;
+#[cfg(feature = "configure")]
+pub use qr::reader::QrDecoder;
+pub use totp::{Algorithm, OtpType, TimeStep, TokenFormat, Totp, TotpError};
+pub use verify::{Verification, Verifier};
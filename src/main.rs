@@ -1,50 +1,117 @@
-mod config;
 mod logging;
-mod output;
-mod qr;
-mod state;
-mod totp;
 
+use std::path::Path;
 use std::sync::Arc;
 use std::thread;
-use std::env;
 
-use clap::Parser;
-use config::{configuration::Args, secrets::ConfigFile};
+use totp_generator::check::check_sources;
+use totp_generator::clock::{Clock, OffsetClock, SystemClock};
+use totp_generator::config::{
+    self, configuration::ConfigCommand, encryption, format::SecretsFormat, secrets::ConfigFile,
+    settings, sources::ConfigSources, watcher,
+};
+use totp_generator::manage::{self, EntryDetails, EntryTable};
+use totp_generator::secret::Secret;
+use totp_generator::verify::Verifier;
 
 #[cfg(feature = "onetime")]
-use output::onetime::one_time_mode;
+use totp_generator::output::onetime::one_time_mode;
 
 #[cfg(feature = "http")]
-use output::web::server::start_server;
+use totp_generator::output::web::server::start_server;
 
 #[cfg(feature = "cli")]
-use output::cui::console::start_console_ui;
+use totp_generator::output::cui::console::start_console_ui;
 
 #[cfg(feature = "configure")]
-use qr::prompt::generate_configuration;
+use totp_generator::qr::prompt::generate_configuration;
 
-use state::State;
+use totp_generator::skew::{ClockSkew, monitor_skew};
+use totp_generator::state::State;
+use std::time::Duration;
+use anyhow::Context;
 use tokio::sync::oneshot;
 use tokio::{signal, task::JoinSet};
 
+/// Opens the secrets file, asking for its passphrase up front when it is encrypted. The passphrase
+/// is remembered in `passphrase` so that it is only asked once for several files
+async fn open_secrets(
+    secrets: String,
+    format: Option<SecretsFormat>,
+    passphrase_file: Option<&Path>,
+    passphrase: &mut Option<Secret<String>>,
+) -> anyhow::Result<ConfigFile> {
+    let mut secrets_cf = ConfigFile::new(secrets);
+    if let Some(format) = format {
+        secrets_cf = secrets_cf.with_format(format);
+    }
+    // A missing or unreadable file is reported when it gets loaded
+    if !secrets_cf.is_encrypted().await.unwrap_or(false) {
+        return Ok(secrets_cf);
+    }
+    let path = secrets_cf.secrets_path.clone();
+    let own = match passphrase {
+        // Files are tried with the passphrase of the first one
+        Some(shared) if secrets_cf.unlock(shared).await? => return Ok(secrets_cf),
+        // TOTP_PASSPHRASE or the passphrase file gave the shared one, a file with its own can only
+        // be asked for it
+        Some(_) => rpassword::prompt_password(format!("Passphrase for {path}: "))
+            .map(Secret::new)
+            .with_context(|| format!("{path} needs its own passphrase, it cannot be asked for"))?,
+        None => {
+            encryption::read_passphrase("TOTP_PASSPHRASE", passphrase_file, "Passphrase: ", false)?
+        }
+    };
+    if !secrets_cf.unlock(&own).await? {
+        anyhow::bail!("Wrong passphrase for {path}");
+    }
+    passphrase.get_or_insert(own);
+    Ok(secrets_cf)
+}
+
+/// Opens every secrets file given, directories standing for the files they contain
+async fn open_sources(
+    paths: &[String],
+    format: Option<SecretsFormat>,
+    passphrase_file: Option<&Path>,
+) -> anyhow::Result<ConfigSources> {
+    let mut passphrase = None;
+    let mut files = Vec::new();
+    for path in ConfigSources::expand_paths(paths)? {
+        files.push(open_secrets(path, format, passphrase_file, &mut passphrase).await?);
+    }
+    ConfigSources::new(files)
+}
+
 #[actix_web::main]
 async fn main() -> anyhow::Result<()> {
-    let args = Args::parse();
+    let (args, settings) = settings::parse_args()?;
     // Need to keep reference to _log otherwise lose the log file
     let _log = logging::setup_tracing(&args.log_file, args.std_err);
+    let clock: Arc<dyn Clock> = Arc::new(OffsetClock::new(SystemClock, args.clock_offset));
+    let passphrase_file = args.passphrase_file.as_deref();
+    let secrets_format = args.secrets_format;
     match args.mode {
-        config::configuration::Mode::OneTime { target, secrets } => {
+        config::configuration::Mode::OneTime {
+            target,
+            secrets,
+            advance,
+            next,
+            tag,
+        } => {
             #[cfg(feature = "onetime")]
             {
-                let mut secrets_cf = ConfigFile::new(secrets.clone());
-                let o = one_time_mode(&mut secrets_cf, &target).await?;
+                let secrets_cf = open_sources(&secrets, secrets_format, passphrase_file).await?;
+                let tag = tag.as_deref();
+                let o = one_time_mode(&secrets_cf, &target, tag, advance, next, clock.as_ref())
+                    .await?;
                 tracing::info!("One time mode outcome: {o}");
                 println!("{o}");
                 Ok(())
             }
             #[cfg(not(feature = "onetime"))]
             {
+                let _ = (advance, next, tag);
                 tracing::warn!(
                     "One-time mode is not enabled in this build. Please enable the 'onetime' feature to use it."
                 );
@@ -62,10 +129,45 @@ async fn main() -> anyhow::Result<()> {
             no_console,
             port,
             lock_after,
+            unlock_password,
             number_style,
+            show_next,
+            verify_window,
+            time_source,
+            tag,
+            max_clock_skew,
+            skew_check_interval,
+            no_watch,
         } => {
             let mut set: JoinSet<()> = JoinSet::new();
-            let secrets_cf = Arc::new(ConfigFile::new(secrets.clone()));
+            let secrets_cf = open_sources(&secrets, secrets_format, passphrase_file).await?;
+            let secrets_cf = Arc::new(secrets_cf);
+            // Dropping the watchers stops them, so they are kept until the interface exits
+            let _watchers: Vec<_> = if no_watch {
+                Vec::new()
+            } else {
+                secrets_cf
+                    .files()
+                    .filter_map(|file| {
+                        let watched = watcher::watch(Arc::clone(file), Duration::from_millis(200));
+                        if let Err(err) = &watched {
+                            tracing::warn!("Cannot watch {}, polling it: {err}", file.secrets_path);
+                        }
+                        watched.ok()
+                    })
+                    .collect()
+            };
+            let clock_skew = Arc::new(ClockSkew::new(max_clock_skew));
+            if let Some(source) = time_source {
+                tracing::info!("Checking clock skew against {source}");
+                // Not added to the join set: this task never ends and must not keep the app alive
+                tokio::spawn(monitor_skew(
+                    source,
+                    Arc::clone(&clock),
+                    Arc::clone(&clock_skew),
+                    Duration::from_secs(skew_check_interval.max(1)),
+                ));
+            }
             let (http_shutdown_tx, http_shutdown_rx) = oneshot::channel::<()>();
             let (ui_shutdown_tx, ui_shutdown_rx) = oneshot::channel::<()>();
             if let Some(bind) = bind {
@@ -73,13 +175,16 @@ async fn main() -> anyhow::Result<()> {
                 #[cfg(feature = "http")]
                 {
                     let web_secrets_cf = Arc::clone(&secrets_cf);
+                    let verifier = Arc::new(Verifier::new(verify_window, Arc::clone(&clock)));
+                    let web_clock = Arc::clone(&clock);
+                    let web_clock_skew = Arc::clone(&clock_skew);
                     tracing::info!("Launching HTTP server at {}:{}", bind, port);
                     let bind = bind.clone();
                     // Due to actix_web not being Send, we have to run this in a separate thread
                     thread::spawn(move || {
                         actix_web::rt::System::new().block_on(async move {
                             tokio::select! {
-                                i = start_server(bind, port, web_secrets_cf) => {
+                                i = start_server(bind, port, web_secrets_cf, verifier, web_clock, web_clock_skew) => {
                                     match i {
                                         Err(err) => {
                                             tracing::error!("HTTP server error'd: {err}")
@@ -104,6 +209,7 @@ async fn main() -> anyhow::Result<()> {
                 }
                 #[cfg(not(feature = "http"))]
                 {
+                    let _ = verify_window;
                     tracing::warn!(
                         "HTTP server is not enabled in this build. Please enable the 'http' feature to use it."
                     );
@@ -118,14 +224,17 @@ async fn main() -> anyhow::Result<()> {
             if !no_console {
                 #[cfg(feature = "cli")]
                 {
-                    let unlock_password = env::var("UNLOCK_PASSWORD").ok();
                     // Default to console UI
-                    let state = State::default(
+                    let mut state = State::default(
                         Arc::clone(&secrets_cf),
                         unlock_password,
                         lock_after,
                         number_style,
+                        show_next,
+                        Arc::clone(&clock),
+                        Arc::clone(&clock_skew),
                     );
+                    state.tag_filter = tag;
                     set.spawn(async move {
                         let _ = start_console_ui(state).await;
                     });
@@ -151,6 +260,202 @@ async fn main() -> anyhow::Result<()> {
             let _ = ui_shutdown_tx.send(());
             Ok(())
         }
+        config::configuration::Mode::Verify {
+            target,
+            code,
+            secrets,
+            window,
+        } => {
+            let secrets_cf = open_sources(&secrets, secrets_format, passphrase_file).await?;
+            let (_, entries) = secrets_cf.load().await?;
+            let index = ConfigFile::get_exact_index(&entries, &target)?;
+            let verifier = Verifier::new(window, clock);
+            let outcome = secrets_cf.verify(index, &entries[index], &code, &verifier).await?;
+            tracing::info!("Verification outcome: {outcome}");
+            println!("{outcome}");
+            if outcome.is_accepted() {
+                Ok(())
+            } else {
+                Err(anyhow::anyhow!("Verification failed"))
+            }
+        }
+        config::configuration::Mode::Check { secrets, json } => {
+            let secrets_cf = open_sources(&secrets, secrets_format, passphrase_file).await?;
+            let report = check_sources(&secrets_cf).await;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                println!("{report}");
+            }
+            match report.exit_code() {
+                0 => Ok(()),
+                code => std::process::exit(code),
+            }
+        }
+        config::configuration::Mode::Encrypt { secrets } => {
+            let secrets_cf =
+                open_secrets(secrets, secrets_format, passphrase_file, &mut None).await?;
+            if secrets_cf.is_encrypted().await? {
+                return Err(anyhow::anyhow!(
+                    "{} is already encrypted, use rekey to change its passphrase",
+                    secrets_cf.secrets_path
+                ));
+            }
+            let passphrase = encryption::read_passphrase(
+                "TOTP_PASSPHRASE",
+                passphrase_file,
+                "New passphrase: ",
+                true,
+            )?;
+            secrets_cf.rewrite(Some(&passphrase)).await?;
+            println!("Encrypted {}", secrets_cf.secrets_path);
+            Ok(())
+        }
+        config::configuration::Mode::Decrypt { secrets } => {
+            let secrets_cf =
+                open_secrets(secrets, secrets_format, passphrase_file, &mut None).await?;
+            if !secrets_cf.is_encrypted().await? {
+                return Err(anyhow::anyhow!("{} is not encrypted", secrets_cf.secrets_path));
+            }
+            secrets_cf.rewrite(None).await?;
+            println!("Decrypted {}", secrets_cf.secrets_path);
+            Ok(())
+        }
+        config::configuration::Mode::Rekey {
+            secrets,
+            new_passphrase_file,
+        } => {
+            let secrets_cf =
+                open_secrets(secrets, secrets_format, passphrase_file, &mut None).await?;
+            if !secrets_cf.is_encrypted().await? {
+                return Err(anyhow::anyhow!(
+                    "{} is not encrypted, use encrypt instead",
+                    secrets_cf.secrets_path
+                ));
+            }
+            let passphrase = encryption::read_passphrase(
+                "TOTP_NEW_PASSPHRASE",
+                new_passphrase_file.as_deref(),
+                "New passphrase: ",
+                true,
+            )?;
+            secrets_cf.rewrite(Some(&passphrase)).await?;
+            println!("Changed the passphrase of {}", secrets_cf.secrets_path);
+            Ok(())
+        }
+        config::configuration::Mode::Add { secrets, entry } => {
+            let entry = manage::new_entry(entry)?;
+            let secrets_cf =
+                open_secrets(secrets, secrets_format, passphrase_file, &mut None).await?;
+            if secrets_cf.create().await? {
+                println!("Created {}", secrets_cf.secrets_path);
+            }
+            let index = secrets_cf.add_entry(&entry).await?;
+            println!("Added {} as #{index}", entry.name);
+            Ok(())
+        }
+        config::configuration::Mode::Remove { target, secrets } => {
+            let secrets_cf =
+                open_secrets(secrets, secrets_format, passphrase_file, &mut None).await?;
+            let entry = secrets_cf.remove_entry_by(&target).await?;
+            println!("Removed {}", entry.name);
+            Ok(())
+        }
+        config::configuration::Mode::Rename {
+            target,
+            name,
+            secrets,
+        } => {
+            let secrets_cf =
+                open_secrets(secrets, secrets_format, passphrase_file, &mut None).await?;
+            let (previous, entry) = secrets_cf
+                .update_entry_by(&target, |entry| entry.name = name)
+                .await?;
+            println!("Renamed {} to {}", previous.name, entry.name);
+            Ok(())
+        }
+        config::configuration::Mode::SetHandle {
+            target,
+            handle,
+            secrets,
+        } => {
+            let secrets_cf =
+                open_secrets(secrets, secrets_format, passphrase_file, &mut None).await?;
+            let (_, entry) = secrets_cf
+                .update_entry_by(&target, |entry| entry.handle = handle)
+                .await?;
+            if entry.handle.is_empty() {
+                println!("Removed the handle of {}", entry.name);
+            } else {
+                println!("Set the handle of {} to {}", entry.name, entry.handle);
+            }
+            Ok(())
+        }
+        config::configuration::Mode::List {
+            secrets,
+            tag,
+            json,
+            reveal,
+        } => {
+            let secrets_cf = open_sources(&secrets, secrets_format, passphrase_file).await?;
+            let (_, entries) = secrets_cf.load().await?;
+            let listed: Vec<_> = entries
+                .iter()
+                .enumerate()
+                .filter(|(_, entry)| tag.as_deref().is_none_or(|tag| entry.has_tag(tag)))
+                .collect();
+            if json {
+                let listed = listed
+                    .iter()
+                    .map(|(_, entry)| manage::entry_json(entry, reveal))
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                println!("{}", serde_json::to_string_pretty(&listed)?);
+            } else {
+                print!("{}", EntryTable { entries: listed, reveal });
+            }
+            Ok(())
+        }
+        config::configuration::Mode::Show {
+            target,
+            secrets,
+            json,
+            reveal,
+        } => {
+            let secrets_cf = open_sources(&secrets, secrets_format, passphrase_file).await?;
+            let (_, entries) = secrets_cf.load().await?;
+            let index = ConfigFile::get_index(&entries, &target)?;
+            let entry = &entries[index];
+            if json {
+                let json = manage::entry_json(entry, reveal)?;
+                println!("{}", serde_json::to_string_pretty(&json)?);
+            } else {
+                print!("{}", EntryDetails { index, entry, reveal });
+            }
+            Ok(())
+        }
+        config::configuration::Mode::Config {
+            command: ConfigCommand::Show,
+        } => {
+            match &settings {
+                Some(settings) => println!("Settings file: {}\n", settings.path.display()),
+                None => match args.config.or_else(settings::default_path) {
+                    Some(path) => println!("Settings file: {} (not found)\n", path.display()),
+                    None => println!("Settings file: none\n"),
+                },
+            }
+            let effective = settings::effective(settings.as_ref())?;
+            let width = effective.iter().map(|value| value.key.len()).max().unwrap_or_default();
+            let value_width = effective
+                .iter()
+                .map(|value| value.values.join(",").chars().count())
+                .max()
+                .unwrap_or_default();
+            for value in effective {
+                let values = value.values.join(",");
+                println!("{:width$}  {values:value_width$}  {}", value.key, value.origin);
+            }
+            Ok(())
+        }
         config::configuration::Mode::Configure {
             from_image,
             prompt,
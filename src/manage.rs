@@ -0,0 +1,298 @@
+//! Managing entries from the command line: building new ones, and listing or showing them without
+//! giving their secrets away
+
+use std::{
+    fmt,
+    io::{self, Write as _},
+};
+
+use crate::{
+    config::{configuration::EntryArgs, otpauth, secrets::ConfigEntry},
+    secret::Secret,
+    totp::{OtpType, TotpError},
+};
+use anyhow::{Context, Result};
+
+/// Builds the entry described by the flags, starting from `--uri` if given. Without a URI the
+/// name and secret are prompted for when missing, and then so are the optional details
+pub fn new_entry(args: EntryArgs) -> Result<ConfigEntry> {
+    let mut prompted = false;
+    let mut entry = match &args.uri {
+        Some(uri) => otpauth::parse(uri)?,
+        None => {
+            let name = match &args.name {
+                Some(name) => name.clone(),
+                None => {
+                    prompted = true;
+                    prompt("Name: ")?.context("A name is required")?
+                }
+            };
+            let secret = match &args.secret {
+                Some(secret) => secret.clone(),
+                None => rpassword::prompt_password("Secret: ")
+                    .context("Cannot prompt for the secret, pass --secret or --uri instead")?,
+            };
+            ConfigEntry::new(name, secret)
+        }
+    };
+    if args.uri.is_some() {
+        entry.name = args.name.unwrap_or(entry.name);
+        entry.secret = args.secret.map(Secret::new).unwrap_or(entry.secret);
+    }
+    entry.handle = args.handle.unwrap_or_default();
+    entry.issuer = args.issuer.or(entry.issuer);
+    entry.account = args.account.or(entry.account);
+    entry.tags = args.tags;
+    entry.notes = args.notes;
+    entry.encoding = args.encoding.unwrap_or(entry.encoding);
+    entry.otp_type = args.otp_type.unwrap_or(entry.otp_type);
+    entry.pin = args.pin.map(Secret::new).or(entry.pin);
+    entry.algorithm = args.algorithm.unwrap_or(entry.algorithm);
+    entry.digits = args.digits.unwrap_or(entry.digits);
+    entry.timestep = args.timestep.unwrap_or(entry.timestep);
+    entry.t0 = args.t0.unwrap_or(entry.t0);
+    entry.counter = args.counter.unwrap_or(entry.counter);
+    if prompted {
+        if entry.handle.is_empty() {
+            entry.handle = prompt("Handle (optional): ")?.unwrap_or_default();
+        }
+        if entry.issuer.is_none() {
+            entry.issuer = prompt("Issuer (optional): ")?;
+        }
+        if entry.account.is_none() {
+            entry.account = prompt("Account (optional): ")?;
+        }
+        if entry.tags.is_empty() {
+            let tags = prompt("Tags, comma separated (optional): ")?.unwrap_or_default();
+            entry.tags = tags
+                .split(',')
+                .map(|tag| tag.trim().to_owned())
+                .filter(|tag| !tag.is_empty())
+                .collect();
+        }
+    }
+    if entry.validate() == Err(TotpError::MissingPin) {
+        let pin = rpassword::prompt_password("PIN: ")
+            .context("Cannot prompt for the PIN, pass --pin instead")?;
+        entry.pin = Some(Secret::new(pin));
+    }
+    entry.validate()?;
+    Ok(entry)
+}
+
+/// Reads a line from stdin, `None` when it is empty
+fn prompt(message: &str) -> Result<Option<String>> {
+    print!("{message}");
+    io::stdout().flush()?;
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    let input = input.trim();
+    Ok((!input.is_empty()).then(|| input.to_owned()))
+}
+
+/// Entries as printed by `list`, one row each
+pub struct EntryTable<'a> {
+    pub entries: Vec<(usize, &'a ConfigEntry)>,
+    pub reveal: bool,
+}
+
+impl fmt::Display for EntryTable<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut header = vec!["#", "NAME", "HANDLE", "ISSUER", "ACCOUNT", "TYPE", "TAGS"];
+        if self.reveal {
+            header.push("SECRET");
+        }
+        let mut rows = vec![header.into_iter().map(str::to_owned).collect::<Vec<_>>()];
+        for (index, entry) in &self.entries {
+            let mut row = vec![
+                index.to_string(),
+                entry.name.clone(),
+                entry.handle.clone(),
+                entry.issuer.clone().unwrap_or_default(),
+                entry.account.clone().unwrap_or_default(),
+                entry.otp_type.to_string(),
+                entry.tags.join(","),
+            ];
+            if self.reveal {
+                row.push(reveal_secret(entry));
+            }
+            rows.push(row);
+        }
+        let widths: Vec<usize> = (0..rows[0].len())
+            .map(|column| {
+                rows.iter()
+                    .map(|row| row[column].chars().count())
+                    .max()
+                    .unwrap_or_default()
+            })
+            .collect();
+        for row in rows {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{cell:width$}"))
+                .collect();
+            writeln!(f, "{}", cells.join("  ").trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+/// Every field of an entry as printed by `show`
+pub struct EntryDetails<'a> {
+    pub index: usize,
+    pub entry: &'a ConfigEntry,
+    pub reveal: bool,
+}
+
+impl fmt::Display for EntryDetails<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let entry = self.entry;
+        let optional = |value: &Option<String>| value.clone().unwrap_or_default();
+        let secret = match (self.reveal, entry.secret_source()) {
+            (true, _) => reveal_secret(entry),
+            (false, Ok(Some(source))) => format!("[hidden] read from {source}"),
+            (false, _) => "[hidden]".to_owned(),
+        };
+        let mut fields = vec![
+            ("index", self.index.to_string()),
+            ("name", entry.name.clone()),
+            ("handle", entry.handle.clone()),
+            ("source", optional(&entry.source)),
+            ("issuer", optional(&entry.issuer)),
+            ("account", optional(&entry.account)),
+            ("tags", entry.tags.join(", ")),
+            ("type", entry.otp_type.to_string()),
+            ("secret", secret),
+            ("encoding", entry.encoding.to_string()),
+            ("algorithm", entry.algorithm.to_string()),
+            ("digits", entry.digits.to_string()),
+            ("timestep", entry.timestep.to_string()),
+            ("t0", entry.t0.to_string()),
+        ];
+        if entry.otp_type == OtpType::Hotp {
+            fields.push(("counter", entry.counter.to_string()));
+        }
+        if let Some(pin) = &entry.pin {
+            let pin = if self.reveal {
+                pin.expose().clone()
+            } else {
+                "[hidden]".to_owned()
+            };
+            fields.push(("pin", pin));
+        }
+        fields.push(("notes", optional(&entry.notes)));
+        for (name, value) in fields.into_iter().filter(|(_, value)| !value.is_empty()) {
+            writeln!(f, "{name:>9}: {value}")?;
+        }
+        Ok(())
+    }
+}
+
+/// JSON of an entry for `list` and `show`, as it would be written in a JSON secrets file but
+/// without its secret and PIN unless `reveal`, in which case the secret is read from wherever it
+/// is kept and written in place of its source
+pub fn entry_json(entry: &ConfigEntry, reveal: bool) -> Result<serde_json::Value> {
+    let mut shown = entry.clone();
+    if reveal {
+        shown.secret = entry.secret()?;
+        shown.secret_env = None;
+        shown.secret_file = None;
+        shown.secret_command = None;
+    } else {
+        shown.secret = Secret::new(String::new());
+        shown.pin = None;
+    }
+    Ok(serde_json::to_value(shown)?)
+}
+
+fn reveal_secret(entry: &ConfigEntry) -> String {
+    match entry.secret() {
+        Ok(secret) => secret.expose().clone(),
+        Err(err) => format!("[{err}]"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+    use crate::{
+        config::configuration::{Args, Mode},
+        totp::SecretEncoding,
+    };
+
+    fn add(flags: &[&str]) -> Result<ConfigEntry> {
+        let command = ["totp-generator", "add", "--secrets", "secrets.json", "--name", "Test"];
+        let Mode::Add { entry, .. } = Args::try_parse_from(command.iter().chain(flags))?.mode else {
+            unreachable!()
+        };
+        new_entry(entry)
+    }
+
+    #[test]
+    fn test_new_entry_flags() {
+        let hex = add(&["--secret", "48656c6c6f21deadbeef", "--encoding", "hex"]).unwrap();
+        assert_eq!(hex.encoding, SecretEncoding::Hex);
+        assert!(add(&["--secret", "48656c6c6f21deadbeef", "--encoding", "rot13"]).is_err());
+
+        let shifted = add(&["--secret", "JBSWY3DPEHPK3PXP", "--t0", "30"]).unwrap();
+        assert_eq!(shifted.t0, 30);
+
+        let motp = ["--secret", "0123456789abcdef", "--type", "motp", "--pin", "1234"];
+        let motp = add(&motp).unwrap();
+        assert_eq!(motp.pin.unwrap().expose(), "1234");
+    }
+
+    #[test]
+    fn test_listing_hides_secrets() {
+        let entries: Vec<ConfigEntry> = serde_json::from_str(
+            r#"[
+                {"name": "Mail", "handle": "mail", "secret": "JBSWY3DPEHPK3PXP", "tags": ["a"]},
+                {"name": "VPN", "secret": "GEZDGNBVGY3TQOJQ", "pin": "1234", "notes": "desk"}
+            ]"#,
+        )
+        .unwrap();
+        let table = |reveal| {
+            EntryTable {
+                entries: entries.iter().enumerate().collect(),
+                reveal,
+            }
+            .to_string()
+        };
+        assert_eq!(
+            table(false),
+            "#  NAME  HANDLE  ISSUER  ACCOUNT  TYPE  TAGS\n\
+             0  Mail  mail                     totp  a\n\
+             1  VPN                            totp\n"
+        );
+        assert!(table(true).contains("GEZDGNBVGY3TQOJQ"));
+
+        let details = |reveal| {
+            EntryDetails {
+                index: 1,
+                entry: &entries[1],
+                reveal,
+            }
+            .to_string()
+        };
+        assert!(
+            details(false).contains("   secret: [hidden]\n"),
+            "{}",
+            details(false)
+        );
+        assert!(!details(false).contains("1234"));
+        assert!(details(true).contains("      pin: 1234\n"));
+        assert!(details(false).contains("    notes: desk\n"));
+
+        let json = entry_json(&entries[1], false).unwrap().to_string();
+        assert!(
+            !json.contains("GEZDGNBVGY3TQOJQ") && json.contains("desk"),
+            "{json}"
+        );
+        let json = entry_json(&entries[1], true).unwrap().to_string();
+        assert!(json.contains("GEZDGNBVGY3TQOJQ"), "{json}");
+
+    }
+}
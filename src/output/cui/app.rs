@@ -8,12 +8,17 @@ use ratatui::{
     widgets::{Block, Borders, Paragraph},
     Frame, Terminal,
 };
-use std::{io, time::SystemTime};
-use tokio::time::{self, Duration};
+use std::{io, sync::Arc};
+use tokio::{
+    sync::watch,
+    time::{self, Duration},
+};
 use tokio_stream::StreamExt;
 
 use crate::{
-    config::secrets::ConfigEntry, output::cui::input::keyboard::KeyboardAction, state::State,
+    config::secrets::{Change, ConfigEntry},
+    output::cui::input::keyboard::KeyboardAction,
+    state::State,
 };
 
 use super::components::{messages::Messages, totp_box::TotpBox};
@@ -22,7 +27,17 @@ pub struct App {
     pub totps: Vec<TotpBox>,
     pub state: State,
     secrets: Vec<ConfigEntry>,
+    /// Indexes in `secrets` of the entries matching the tag filter, in display order
+    shown: Vec<usize>,
     messages: Messages,
+    /// Set after pressing `+`, the next identifier pressed advances that HOTP entry
+    pub advance_pending: bool,
+    /// Tag being typed after pressing `/`
+    pub filter_input: Option<String>,
+    /// Reloads of watched secrets files and edits made by this process; unwatched files are
+    /// checked on every tick instead
+    changes: Vec<watch::Receiver<Change>>,
+    loaded: bool,
 }
 
 #[cfg(feature = "cli")]
@@ -30,9 +45,14 @@ impl App {
     pub fn new(state: State) -> App {
         App {
             totps: vec![],
+            changes: state.secrets_cf.subscribe(),
             state,
             secrets: vec![],
+            shown: vec![],
             messages: Messages::new(),
+            advance_pending: false,
+            filter_input: None,
+            loaded: false,
         }
     }
 
@@ -43,6 +63,43 @@ impl App {
         self.messages.push(format!("[{}] {message}", out));
     }
 
+    /// Shows only the entries with `tag`, or every entry for `None`
+    pub fn set_tag_filter(&mut self, tag: Option<String>) {
+        self.state.tag_filter = tag;
+        self.apply_filter();
+    }
+
+    fn apply_filter(&mut self) {
+        let tag = self.state.tag_filter.as_deref();
+        let shown: Vec<usize> = (0..self.secrets.len())
+            .filter(|&index| tag.is_none_or(|tag| self.secrets[index].has_tag(tag)))
+            .collect();
+        // Boxes are matched by position, which the filter may change
+        if shown != self.shown {
+            self.shown = shown;
+            self.totps.clear();
+        }
+    }
+
+    /// `index` is the position of the box on screen
+    pub async fn advance_counter(&mut self, index: usize) {
+        let Some(&index) = self.shown.get(index) else {
+            return;
+        };
+        let Some(entry) = self.secrets.get(index) else {
+            return;
+        };
+        match self.state.secrets_cf.advance_counter(index, entry).await {
+            Ok(entry) => {
+                self.add_message(format!("Advanced {} to counter {}", entry.name, entry.counter));
+            }
+            Err(err) => {
+                tracing::warn!("Failed to advance counter {err}");
+                self.add_message(format!("[E] Failed to advance counter: {err}"));
+            }
+        }
+    }
+
     pub async fn totp_changed(&mut self) {
         let mut interval = time::interval(Duration::from_millis(50));
         interval.set_missed_tick_behavior(time::MissedTickBehavior::Burst);
@@ -90,19 +147,27 @@ impl App {
     }
 
     fn render(&mut self, frame: &mut Frame) {
-        let now = SystemTime::now();
-        if let Some(since) = self.state.unlocked_since {
-            // We aren't locked but it's time to lock
-            if let Some(d) = self.state.lock_after {
-                if now.duration_since(since).expect("Issue computing duration") >= d {
-                    self.lock();
-                    return self.render(frame);
-                }
-            }
-            self.render_normal_screen(frame);
-        } else {
+        if self.lock_if_expired() || self.is_locked() {
             self.render_locked_screen(frame)
+        } else {
+            self.render_normal_screen(frame);
+        }
+    }
+
+    /// Locks the UI once it has been unlocked for longer than `lock_after`, returns whether it
+    /// just got locked
+    pub fn lock_if_expired(&mut self) -> bool {
+        let (Some(since), Some(lock_after)) = (self.state.unlocked_since, self.state.lock_after)
+        else {
+            return false;
+        };
+        // A clock going backwards counts as no time elapsed
+        let elapsed = self.state.clock.now().duration_since(since).unwrap_or_default();
+        if elapsed >= lock_after {
+            self.lock();
+            return true;
         }
+        false
     }
 
     fn get_row_and_column_constraints(&self) -> (Vec<Constraint>, Vec<Constraint>) {
@@ -115,7 +180,27 @@ impl App {
         let [messages_row, totps_row]: [Rect; 2] =
             Layout::vertical([Constraint::Length(1), Constraint::Fill(1)]).areas(frame.area());
         self.render_totps(totps_row, frame);
-        frame.render_widget(Paragraph::new(self.messages.last()), messages_row);
+        if let Some(input) = &self.filter_input {
+            let prompt = Line::from(vec![
+                Span::raw("Tag: "),
+                Span::styled(format!("{input}_"), Style::default().add_modifier(Modifier::BOLD)),
+            ]);
+            frame.render_widget(Paragraph::new(prompt), messages_row);
+            return;
+        }
+        let mut line = Vec::new();
+        if let Some(tag) = &self.state.tag_filter {
+            line.push(Span::styled(format!("#{tag} "), Style::default().fg(Color::Cyan)));
+        }
+        // The skew warning stays on screen for as long as it is relevant
+        if let Some(warning) = self.state.clock_skew.warning() {
+            line.push(Span::styled(
+                format!("⚠ {warning} "),
+                Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+            ));
+        }
+        line.push(Span::raw(self.messages.last()));
+        frame.render_widget(Paragraph::new(Line::from(line)), messages_row);
     }
 
     fn render_totps(&mut self, rect: Rect, frame: &mut Frame) {
@@ -137,6 +222,7 @@ impl App {
                     *col,
                     i,
                     self.state.number_style.clone(),
+                    self.state.show_next,
                 );
                 i += 1;
             }
@@ -148,7 +234,7 @@ impl App {
     }
 
     pub fn unlock(&mut self) {
-        self.state.unlocked_since = Some(SystemTime::now());
+        self.state.unlocked_since = Some(self.state.clock.now());
     }
 
     pub fn lock(&mut self) {
@@ -156,42 +242,51 @@ impl App {
     }
 
     fn get_rows_and_columns(&self) -> (u8, u8) {
-        match self.secrets.len() {
-            n if n <= 4 => (2, 2),
-            n if n <= 6 => (2, 3),
-            n if n <= 9 => (3, 3),
-            n if n <= 12 => (3, 4),
-            n if n <= 16 => (4, 4),
-            n if n <= 20 => (4, 5),
-            _ => (4, 6),
-        }
+        grid_size(self.shown.len())
     }
 
     async fn update_totps(&mut self) -> bool {
         let mut has_changed = false;
-        match self.state.secrets_cf.load().await {
-            Err(err) => {
+        let mut notified = false;
+        let mut reloaded = false;
+        for changes in self.changes.iter_mut() {
+            if changes.has_changed().unwrap_or(false) {
+                reloaded |= *changes.borrow_and_update() == Change::Reloaded;
+                notified = true;
+            }
+        }
+        if notified || !self.loaded || !self.state.secrets_cf.is_watched() {
+            self.loaded = true;
+            // Each source fails on its own, the others keep being displayed
+            let loaded = self.state.secrets_cf.load_all().await;
+            for err in loaded.errors {
                 tracing::error!("Error loading secrets file {err}");
-                self.secrets = vec![];
                 self.add_message(format!("Error loading secrets file {err}"));
             }
-            Ok((changed, entries)) => {
-                has_changed = changed;
-                if changed {
-                    self.secrets = entries;
-                    self.add_message("Secrets file has changed, reloading".to_owned());
-                }
+            // A watcher has already reloaded the entries by the time it notifies
+            if loaded.changed || reloaded {
+                self.secrets = loaded.entries;
+                self.apply_filter();
+                // Rebuild every box, any field (e.g. a fixed secret) may have changed
+                self.totps.clear();
+                self.add_message("Secrets file has changed, reloading".to_owned());
+                has_changed = true;
+            } else if notified {
+                // Only the boxes of the entries this process edited are rebuilt below
+                self.secrets = loaded.entries;
+                self.apply_filter();
+                has_changed = true;
             }
-        };
+        }
         let secrets = &self.secrets;
-        if secrets.len() != self.totps.len() {
-            self.totps.truncate(secrets.len());
+        if self.shown.len() != self.totps.len() {
+            self.totps.truncate(self.shown.len());
             has_changed = true;
         }
-        for (i, entry) in secrets.iter().enumerate() {
+        for (i, entry) in self.shown.iter().map(|&index| &secrets[index]).enumerate() {
             if let Some(existing) = self.totps.get_mut(i) {
-                if entry.handle != existing.code {
-                    self.totps[i] = TotpBox::from(entry);
+                if entry.handle != existing.code || entry.counter != existing.counter() {
+                    self.totps[i] = TotpBox::new(entry, Arc::clone(&self.state.clock));
                     has_changed = true;
                 } else if existing.needs_refresh() {
                     existing.refresh();
@@ -202,7 +297,7 @@ impl App {
                     has_changed = true;
                 }
             } else {
-                self.totps.insert(i, TotpBox::from(entry));
+                self.totps.insert(i, TotpBox::new(entry, Arc::clone(&self.state.clock)));
                 has_changed = true;
             }
         }
@@ -237,6 +332,9 @@ pub async fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> i
                                 tracing::warn!("{}", m);
                                 app.add_message(format!("[E] {}", m));
                             },
+                            KeyboardAction::AdvanceCounter(index) => {
+                                app.advance_counter(index).await;
+                            },
                             KeyboardAction::NoOp => {
                                 tracing::debug!("No op");
                             },
@@ -251,3 +349,135 @@ pub async fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> i
         }
     }
 }
+
+/// Rows and columns of the grid for `count` boxes, up to one box per key of
+/// [`ENTRY_KEYS`](super::ENTRY_KEYS)
+fn grid_size(count: usize) -> (u8, u8) {
+    match count {
+        n if n <= 4 => (2, 2),
+        n if n <= 6 => (2, 3),
+        n if n <= 9 => (3, 3),
+        n if n <= 12 => (3, 4),
+        n if n <= 16 => (4, 4),
+        n if n <= 20 => (4, 5),
+        n if n <= 24 => (4, 6),
+        _ => (5, 6),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{
+        clock::FakeClock,
+        config::{configuration::NumberStyle, secrets::ConfigFile, sources::ConfigSources},
+        skew::ClockSkew,
+    };
+
+    #[test]
+    fn test_grid_has_a_box_per_key() {
+        for count in 1..=crate::output::cui::ENTRY_KEYS.len() {
+            let (rows, columns) = grid_size(count);
+            assert!(usize::from(rows * columns) >= count, "{count} entries");
+        }
+    }
+
+    #[test]
+    fn test_locks_after_inactivity() {
+        let clock = Arc::new(FakeClock::at(1748742637));
+        let state = State::default(
+            Arc::new(ConfigSources::single(ConfigFile::new("unused.json".to_owned()))),
+            None,
+            300,
+            NumberStyle::Standard,
+            5,
+            clock.clone(),
+            Arc::new(ClockSkew::new(5)),
+        );
+        let mut app = App::new(state);
+
+        clock.advance(Duration::from_secs(299));
+        assert!(!app.lock_if_expired());
+        assert!(!app.is_locked());
+
+        clock.advance(Duration::from_secs(1));
+        assert!(app.lock_if_expired());
+        assert!(app.is_locked());
+
+        // Unlocking restarts the timer
+        app.unlock();
+        clock.advance(Duration::from_secs(299));
+        assert!(!app.lock_if_expired());
+    }
+
+    #[tokio::test]
+    async fn test_tag_filter() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("secrets.json");
+        std::fs::write(
+            &path,
+            r#"[
+                {"name": "Mail", "secret": "JBSWY3DPEHPK3PXP", "tags": ["personal"]},
+                {"name": "AWS prod", "secret": "JBSWY3DPEHPK3PXP", "tags": ["work", "prod"]},
+                {"name": "AWS staging", "secret": "JBSWY3DPEHPK3PXP", "tags": ["Work"]}
+            ]"#,
+        )
+        .unwrap();
+        let state = State::default(
+            Arc::new(ConfigSources::single(ConfigFile::new(path.to_string_lossy().into_owned()))),
+            None,
+            0,
+            NumberStyle::Standard,
+            5,
+            Arc::new(FakeClock::at(1748742637)),
+            Arc::new(ClockSkew::new(5)),
+        );
+        let mut app = App::new(state);
+        let names = |app: &App| -> Vec<String> {
+            app.totps.iter().map(|totp| totp.name.clone()).collect()
+        };
+
+        assert!(app.update_totps().await);
+        assert_eq!(names(&app), ["Mail", "AWS prod", "AWS staging"]);
+        app.set_tag_filter(Some("work".to_owned()));
+        assert!(app.update_totps().await);
+        assert_eq!(names(&app), ["AWS prod", "AWS staging"]);
+        app.set_tag_filter(None);
+        app.update_totps().await;
+        assert_eq!(app.totps.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_own_edits_are_not_reloads() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("secrets.json");
+        std::fs::write(
+            &path,
+            r#"[
+                {"name": "Mail", "secret": "JBSWY3DPEHPK3PXP"},
+                {"name": "Bank", "secret": "JBSWY3DPEHPK3PXP", "type": "hotp"}
+            ]"#,
+        )
+        .unwrap();
+        let secrets_cf = ConfigFile::new(path.to_string_lossy().into_owned());
+        let state = State::default(
+            Arc::new(ConfigSources::single(secrets_cf)),
+            None,
+            0,
+            NumberStyle::Standard,
+            5,
+            Arc::new(FakeClock::at(1748742637)),
+            Arc::new(ClockSkew::new(5)),
+        );
+        let mut app = App::new(state);
+        assert!(app.update_totps().await);
+
+        app.advance_counter(1).await;
+        assert!(app.update_totps().await);
+        assert_eq!(app.totps[1].counter(), 1);
+        assert!(app.messages.last().contains("Advanced Bank to counter 1"));
+        assert!(!app.update_totps().await);
+    }
+}
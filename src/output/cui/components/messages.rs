@@ -1,3 +1,4 @@
+#[derive(Default)]
 pub struct Messages {
     messages: Vec<String>
 }
@@ -1,46 +1,83 @@
 use ratatui::{
     layout::{Alignment, Constraint, Flex, Layout, Rect},
-    style::{Color, Style},
+    style::{Color, Modifier, Style},
     text::{Line, Span},
     widgets::{Block, Borders, Gauge, Paragraph},
     Frame,
 };
 
+use std::sync::Arc;
+
 use crate::{
+    clock::Clock,
     config::{configuration::NumberStyle, secrets::ConfigEntry},
-    output::cui::numbers::{pipe::big_number_font, utf8::utf8_font},
-    totp::Totp,
+    generator,
+    output::cui::{
+        numbers::{lite::lite_font, pipe::big_number_font, utf8::utf8_font},
+        ENTRY_KEYS,
+    },
+    secret::Secret,
+    totp::{TimeStep, Totp, TotpError},
 };
 
 #[derive(Debug)]
 pub struct TotpBox {
-    totp: Totp,
+    /// Entries that cannot produce a token keep their error so the box can display it
+    totp: Result<Totp, TotpError>,
     pub name: String,
     pub code: String,
-    secret: String,
-    pub digits: u8,
-    pub timestep: u16,
-    pub valid_duration_seconds: u16,
+    /// Parameters of the entry; its secret is not kept, only the key decoded from it. Secrets kept
+    /// elsewhere, e.g. in `secret_file`, are resolved again at each new time step
+    entry: ConfigEntry,
+    key: Option<Secret<Vec<u8>>>,
+    /// As given by the entry's generator, `None` for counter based entries
+    time_step: Option<TimeStep>,
+    pub valid_duration_seconds: u64,
+    clock: Arc<dyn Clock>,
 }
 
-impl From<&ConfigEntry> for TotpBox {
-    fn from(entry: &ConfigEntry) -> Self {
-        let totp = Totp::new(entry.secret.as_str(), entry.timestep, entry.digits);
+impl TotpBox {
+    pub fn new(entry: &ConfigEntry, clock: Arc<dyn Clock>) -> Self {
+        let key = entry.key();
+        let mut stored = entry.clone();
+        stored.secret = Secret::default();
+        let totp = key
+            .as_ref()
+            .map_err(Clone::clone)
+            .and_then(|key| generator::generate_with_key(entry, key, clock.as_ref()));
         TotpBox {
             name: entry.name.clone(),
             code: entry.handle.clone(),
-            digits: entry.digits,
-            secret: entry.secret.clone(),
-            timestep: entry.timestep,
-            valid_duration_seconds: totp.valid_duration(),
+            entry: stored,
+            key: key.ok(),
+            time_step: entry
+                .generator()
+                .ok()
+                .and_then(|generator| generator.time_step(entry)),
+            valid_duration_seconds: totp
+                .as_ref()
+                .map_or(0, |totp| totp.valid_duration(clock.as_ref())),
             totp,
+            clock,
         }
     }
-}
 
-impl TotpBox {
-    pub fn valid_duration(&self) -> u16 {
-        self.totp.valid_duration()
+    /// Stored counter of the entry the box was built from
+    pub fn counter(&self) -> u64 {
+        self.entry.counter
+    }
+
+    /// Token of the next time step, once the current one has no more than `show_next` seconds
+    /// left
+    pub fn upcoming_token(&self, show_next: u16) -> Option<&str> {
+        let next_token = self.totp.as_ref().ok()?.next_token.as_deref()?;
+        (self.valid_duration() <= show_next.into()).then_some(next_token)
+    }
+
+    pub fn valid_duration(&self) -> u64 {
+        self.totp
+            .as_ref()
+            .map_or(0, |totp| totp.valid_duration(self.clock.as_ref()))
     }
 
     pub fn render(
@@ -49,6 +86,7 @@ impl TotpBox {
         area: Rect,
         index: u8,
         number_style: NumberStyle,
+        show_next: u16,
     ) {
         let size = area.as_size();
         let block = Block::default()
@@ -71,7 +109,7 @@ impl TotpBox {
         let [top_left, _tc, top_right]: [Rect; 3] = Layout::horizontal([
             Constraint::Length(10), // Top left (number)
             Constraint::Min(0),
-            Constraint::Length(10), // Top right
+            Constraint::Length(24), // Top right, handles may be prefixed by their source
         ])
         .horizontal_margin(1)
         .areas(top_row);
@@ -84,11 +122,29 @@ impl TotpBox {
             .flex(Flex::Center)
             .areas(second_row);
         let main_content = if let Some(t) = totp_box {
-            match number_style {
-                NumberStyle::Utf8 => font_to_lines(utf8_font(t.totp.token.as_str())),
-                NumberStyle::Standard => vec![Line::from(t.totp.token.clone())],
-                NumberStyle::Pipe => font_to_lines(big_number_font(t.totp.token.as_str())),
-                NumberStyle::Lite => render_lite_number_lines(t.totp.token.as_str()),
+            match &t.totp {
+                Ok(totp) => {
+                    let mut lines = match number_style {
+                        NumberStyle::Utf8 => font_to_lines(utf8_font(totp.token.as_str())),
+                        NumberStyle::Standard => vec![Line::from(totp.token.clone())],
+                        NumberStyle::Pipe => font_to_lines(big_number_font(totp.token.as_str())),
+                        NumberStyle::Lite => font_to_lines(lite_font(totp.token.as_str())),
+                    };
+                    // Close to the rollover, show what comes next so it can be typed right away
+                    if let Some(next_token) = t.upcoming_token(show_next) {
+                        lines.push(Line::styled(
+                            format!("next {next_token}"),
+                            Style::default()
+                                .fg(Color::DarkGray)
+                                .add_modifier(Modifier::DIM),
+                        ));
+                    }
+                    lines
+                }
+                Err(err) => vec![Line::styled(
+                    format!("Invalid entry: {err}"),
+                    Style::default().fg(Color::Red),
+                )],
             }
         } else {
             vec![Line::from("N/A".to_owned())]
@@ -117,8 +173,45 @@ impl TotpBox {
                 Paragraph::new(content).alignment(Alignment::Right),
                 top_right,
             );
-            frame.render_widget(Paragraph::new(t.name.clone()), second_cell);
-            let ratio = t.valid_duration() as f64 / (t.timestep as f64);
+            let mut name = vec![Span::raw(t.name.clone())];
+            if let Some(source) = &t.entry.source {
+                name.push(Span::styled(
+                    format!(" ({source})"),
+                    Style::default().fg(Color::DarkGray),
+                ));
+            }
+            // Second line tells apart entries with similar names, e.g. several AWS accounts
+            let entry = &t.entry;
+            let mut details: Vec<Span> = [&entry.issuer, &entry.account]
+                .into_iter()
+                .flatten()
+                .map(|detail| Span::raw(format!("{detail} ")))
+                .collect();
+            details.extend(
+                entry
+                    .tags
+                    .iter()
+                    .map(|tag| Span::styled(format!("#{tag} "), Style::default().fg(Color::Cyan))),
+            );
+            let lines = vec![
+                Line::from(name),
+                Line::from(details).style(Style::default().fg(Color::DarkGray)),
+            ];
+            frame.render_widget(Paragraph::new(lines), second_cell);
+            let Ok(totp) = &t.totp else {
+                return;
+            };
+            let Some(time_step) = t.time_step else {
+                // Counter based tokens do not expire, show the counter instead of the validity
+                // gauge
+                frame.render_widget(
+                    Paragraph::new(format!("Counter {}", totp.counter()))
+                        .alignment(Alignment::Right),
+                    bottom_cell,
+                );
+                return;
+            };
+            let ratio = t.valid_duration() as f64 / (time_step.seconds as f64);
             let validity_label = format!("{}s", t.valid_duration());
             let validity_widget = Gauge::default()
                 .gauge_style(Style::default().fg(Color::Green))
@@ -136,79 +229,136 @@ impl TotpBox {
         }
     }
 
-    pub fn get_token(&self) -> String {
-        self.totp.token.clone()
+    pub fn get_token(&self) -> Result<String, TotpError> {
+        self.totp
+            .as_ref()
+            .map(|totp| totp.token.clone())
+            .map_err(Clone::clone)
     }
     pub fn needs_refresh(&mut self) -> bool {
+        let (Ok(totp), Some(time_step)) = (&self.totp, self.time_step) else {
+            return false;
+        };
         self.valid_duration_seconds != self.valid_duration()
-            || self.totp.needs_refresh(self.timestep)
+            || totp.needs_refresh(time_step, self.clock.as_ref())
     }
 
     pub fn refresh(&mut self) {
         // Refreshing the box doesn't necessarily mean we refresh the TOTP if it still valid
-        self.valid_duration_seconds = self.valid_duration();
-        if self.totp.needs_refresh(self.timestep) {
-            self.totp
-                .refresh(self.secret.as_str(), self.timestep, self.digits);
+        let clock = self.clock.as_ref();
+        if let Ok(totp) = &self.totp
+            && let Some(time_step) = self.time_step
+            && totp.needs_refresh(time_step, clock)
+        {
+            if matches!(self.entry.secret_source(), Ok(Some(_))) {
+                // Only read again once modified, see SecretCache
+                match self.entry.key() {
+                    Ok(key) => self.key = Some(key),
+                    Err(err) => tracing::warn!("Keeping the key of {}: {}", self.name, err),
+                }
+            }
+            if let Some(key) = &self.key {
+                self.totp = generator::generate_with_key(&self.entry, key, clock);
+            }
         }
+        // Only read the remaining duration once the token is current, otherwise a rollover
+        // would leave the box needing another refresh
+        self.valid_duration_seconds = self.valid_duration();
     }
 }
 
 fn index_to_char(index: u8) -> Option<char> {
-    "0123456789abcdefghijklmnop".chars().nth(index.into())
+    ENTRY_KEYS.chars().nth(index.into())
 }
 
 fn font_to_lines(font: Vec<String>) -> Vec<Line<'static>> {
     font.into_iter().map(|l| Line::from(Span::raw(l))).collect()
 }
 
-fn render_lite_number_lines(input: &str) -> Vec<Line<'static>> {
-    let digits = [
-        [
-            "  ___  ", " / _ \\ ", "| | | |", "| | | |", "| |_| |", " \\___/ ",
-        ], // 0
-        [" __ ", "/_ |", " | |", " | |", " | |", " |_|"], // 1
-        [" ___  ", "|__ \\ ", "   ) |", "  / / ", " / /_ ", "|____|"], // 2
-        [
-            " ____  ", "|___ \\ ", "  __) |", " |__ < ", " ___) |", "|____/ ",
-        ], // 3
-        [
-            " _  _   ", "| || |  ", "| || |_ ", "|__   _|", "   | |  ", "   |_|  ",
-        ], // 4
-        [
-            " _____ ", "| ____|", "| |__  ", "|___ \\ ", " ___) |", "|____/ ",
-        ], // 5
-        [
-            "   __  ", "  / /  ", " / /_  ", "| '_ \\ ", "| (_) |", " \\___/ ",
-        ], // 6
-        [
-            " ______", "|____  |", "    / / ", "   / /  ", "  / /   ", " /_/    ",
-        ], // 7
-        [
-            "  ___  ", " / _ \\ ", "| (_) |", " > _ < ", "| (_) |", " \\ _ / ",
-        ], // 8
-        [
-            "  ___  ", " / _ \\ ", "| (_) |", " \\__, |", "   / / ", "  /_/  ",
-        ], // 9
-    ];
-
-    let mut output = vec!["".to_string(); input.len()];
-
-    for ch in input.chars() {
-        if let Some(d) = ch.to_digit(10) {
-            let digit_lines = &digits[d as usize];
-            for (i, line) in digit_lines.iter().enumerate() {
-                output[i].push_str(line);
-            }
-        } else {
-            for line in &mut output {
-                line.push_str("       "); // space for unknown chars
-            }
-        }
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::*;
+    use crate::{clock::FakeClock, config::secrets::ConfigFile};
+
+    #[test]
+    fn test_rolls_over_at_end_of_window() {
+        let entry: ConfigEntry =
+            serde_json::from_str(r#"{"name": "Test", "secret": "JBSWY3DPEHPK3PXP"}"#).unwrap();
+        let clock = Arc::new(FakeClock::at(1748742637));
+        let mut totp_box = TotpBox::new(&entry, clock.clone());
+        assert_eq!(totp_box.get_token().unwrap(), "879599");
+        assert_eq!(totp_box.valid_duration(), 23);
+        assert!(!totp_box.needs_refresh());
+
+        // Still within the same window: only the remaining duration changes
+        clock.advance(Duration::from_secs(22));
+        assert!(totp_box.needs_refresh());
+        totp_box.refresh();
+        assert_eq!(totp_box.get_token().unwrap(), "879599");
+        assert_eq!(totp_box.valid_duration(), 1);
+
+        clock.advance(Duration::from_secs(1));
+        assert!(totp_box.needs_refresh());
+        totp_box.refresh();
+        assert_eq!(totp_box.get_token().unwrap(), "690726");
+        assert_eq!(totp_box.valid_duration(), 30);
+        assert!(!totp_box.needs_refresh());
+    }
+
+    #[test]
+    fn test_next_token_shown_before_rollover() {
+        let entry: ConfigEntry =
+            serde_json::from_str(r#"{"name": "Test", "secret": "JBSWY3DPEHPK3PXP"}"#).unwrap();
+        let clock = Arc::new(FakeClock::at(1748742637));
+        let mut totp_box = TotpBox::new(&entry, clock.clone());
+        assert_eq!(totp_box.upcoming_token(5), None);
+
+        clock.advance(Duration::from_secs(17));
+        totp_box.refresh();
+        assert_eq!(totp_box.valid_duration(), 6);
+        assert_eq!(totp_box.upcoming_token(5), None);
+
+        clock.advance(Duration::from_secs(1));
+        totp_box.refresh();
+        assert_eq!(totp_box.upcoming_token(5), Some("690726"));
+        assert_eq!(totp_box.upcoming_token(0), None);
+
+        // Counter based entries have no next token
+        let hotp: ConfigEntry = serde_json::from_str(
+            r#"{"name": "Test", "secret": "JBSWY3DPEHPK3PXP", "type": "hotp"}"#,
+        )
+        .unwrap();
+        assert_eq!(TotpBox::new(&hotp, clock).upcoming_token(u16::MAX), None);
     }
 
-    output
-        .into_iter()
-        .map(|l| Line::from(Span::raw(l)))
-        .collect()
+    #[tokio::test]
+    async fn test_rotated_secret_file_used_at_next_step() {
+        let directory = tempfile::tempdir().unwrap();
+        let secret = directory.path().join("secret");
+        std::fs::write(&secret, "GEZDGNBVGY3TQOJQ\n").unwrap();
+        let path = directory.path().join("secrets.json");
+        std::fs::write(&path, r#"[{"name": "Test", "secret_file": "secret"}]"#).unwrap();
+        let secrets_cf = ConfigFile::new(path.to_string_lossy().into_owned());
+        let (_, entries) = secrets_cf.load().await.unwrap();
+        let clock = Arc::new(FakeClock::at(1748742637));
+        let mut totp_box = TotpBox::new(&entries[0], clock.clone());
+        let previous = totp_box.get_token().unwrap();
+
+        std::fs::write(&secret, "JBSWY3DPEHPK3PXP\n").unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&secret)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+        // The current token is kept until the time step ends
+        clock.advance(Duration::from_secs(1));
+        totp_box.refresh();
+        assert_eq!(totp_box.get_token().unwrap(), previous);
+        clock.advance(Duration::from_secs(22));
+        totp_box.refresh();
+        assert_eq!(totp_box.get_token().unwrap(), "690726");
+    }
 }
@@ -1,7 +1,7 @@
 use copypasta::{ClipboardContext, ClipboardProvider};
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 
-use crate::output::cui::app::App;
+use crate::output::cui::{app::App, ENTRY_KEYS, LOCK_KEY};
 
 #[cfg(feature = "cli")]
 impl App {
@@ -17,10 +17,10 @@ impl App {
                     buffer.clear();
                 } else if code == KeyCode::Backspace {
                     buffer.pop();
-                } else if modifiers.is_empty() || modifiers == KeyModifiers::SHIFT {
-                    if let Some(ch) = keyevent_to_char(key) {
-                        buffer.push(ch);
-                    }
+                } else if (modifiers.is_empty() || modifiers == KeyModifiers::SHIFT)
+                    && let Some(ch) = keyevent_to_char(key)
+                {
+                    buffer.push(ch);
                 }
             } else {
                 // Unlock when no password with any key
@@ -32,19 +32,74 @@ impl App {
             return KeyboardAction::NoOp;
         }
 
+        if let Some(input) = &mut self.filter_input {
+            match code {
+                KeyCode::Enter => {
+                    let tag = input.trim().to_owned();
+                    self.filter_input = None;
+                    let message = if tag.is_empty() {
+                        "Showing every entry".to_owned()
+                    } else {
+                        format!("Showing entries tagged {tag}")
+                    };
+                    self.set_tag_filter(Some(tag).filter(|tag| !tag.is_empty()));
+                    return KeyboardAction::Message(message);
+                }
+                KeyCode::Esc => self.filter_input = None,
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                KeyCode::Char(ch) => input.push(ch),
+                _ => {}
+            }
+            return KeyboardAction::NoOp;
+        }
+        if code == KeyCode::Char('/') {
+            self.filter_input = Some(self.state.tag_filter.clone().unwrap_or_default());
+            return KeyboardAction::Message(
+                "Type a tag and press Enter, or clear it to show every entry".to_owned(),
+            );
+        }
         if code == KeyCode::Char('q') {
             return KeyboardAction::Exit("Pressed <q>, Quitting".to_owned());
         }
-        if code == KeyCode::Char('l') {
+        if code == KeyCode::Char(LOCK_KEY) {
             self.lock();
             return KeyboardAction::Message("Manually locked".to_owned());
         }
+        if code == KeyCode::Char('+') {
+            self.advance_pending = true;
+            return KeyboardAction::Message(
+                "Press the identifier of the HOTP entry to advance".to_owned(),
+            );
+        }
+        if std::mem::take(&mut self.advance_pending) {
+            return match keyevent_to_char(key)
+                .and_then(char_to_index)
+                .filter(|i| *i < self.totps.len())
+            {
+                Some(i) => KeyboardAction::AdvanceCounter(i),
+                None => KeyboardAction::ErrorMessage(format!(
+                    "Character {:?} could not be mapped to existing TOTP",
+                    code
+                )),
+            };
+        }
         if let Some(totp) = keyevent_to_char(key)
             .and_then(char_to_index)
             .and_then(|i| self.totps.get(i))
         {
-            if let Err(err) = ClipboardContext::new()
-                .and_then(|mut ctx| ctx.set_contents(totp.get_token()))
+            let token = match totp.get_token() {
+                Ok(token) => token,
+                Err(err) => {
+                    return KeyboardAction::ErrorMessage(format!(
+                        "Cannot copy {}: {}",
+                        totp.name, err
+                    ));
+                }
+            };
+            if let Err(err) =
+                ClipboardContext::new().and_then(|mut ctx| ctx.set_contents(token))
             {
                 KeyboardAction::ErrorMessage(format!(
                     "Failed to copy to clipboard {}",
@@ -69,12 +124,13 @@ fn keyevent_to_char(key_event: KeyEvent) -> Option<char> {
 }
 
 fn char_to_index(ch: char) -> Option<usize> {
-    "0123456789abcdefghijklmnop".find(ch)
+    ENTRY_KEYS.find(ch)
 }
 
 pub enum KeyboardAction {
     Message(String),
     ErrorMessage(String),
+    AdvanceCounter(usize),
     NoOp,
     Exit(String),
 }
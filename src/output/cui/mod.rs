@@ -3,3 +3,9 @@ mod input;
 pub mod numbers;
 pub mod components;
 pub mod console;
+
+/// Keys selecting the boxes of the console UI, in order; entries beyond them cannot be selected
+pub const ENTRY_KEYS: &str = "0123456789abcdefghijklmnop";
+
+/// Locks the console UI, so the entry it maps to in [`ENTRY_KEYS`] cannot be selected
+pub const LOCK_KEY: char = 'l';
@@ -0,0 +1,187 @@
+/// Rows of a single glyph; letters cover the Steam Guard alphabet
+fn glyph(ch: char) -> Option<[&'static str; 6]> {
+    Some(match ch.to_ascii_uppercase() {
+        '0' => [
+            "  ___  ", " / _ \\ ", "| | | |", "| | | |", "| |_| |", " \\___/ ",
+        ],
+        '1' => [" __ ", "/_ |", " | |", " | |", " | |", " |_|"],
+        '2' => [" ___  ", "|__ \\ ", "   ) |", "  / / ", " / /_ ", "|____|"],
+        '3' => [
+            " ____  ", "|___ \\ ", "  __) |", " |__ < ", " ___) |", "|____/ ",
+        ],
+        '4' => [
+            " _  _   ", "| || |  ", "| || |_ ", "|__   _|", "   | |  ", "   |_|  ",
+        ],
+        '5' => [
+            " _____ ", "| ____|", "| |__  ", "|___ \\ ", " ___) |", "|____/ ",
+        ],
+        '6' => [
+            "   __  ", "  / /  ", " / /_  ", "| '_ \\ ", "| (_) |", " \\___/ ",
+        ],
+        '7' => [
+            " ______ ", "|____  |", "    / / ", "   / /  ", "  / /   ", " /_/    ",
+        ],
+        '8' => [
+            "  ___  ", " / _ \\ ", "| (_) |", " > _ < ", "| (_) |", " \\ _ / ",
+        ],
+        '9' => [
+            "  ___  ", " / _ \\ ", "| (_) |", " \\__, |", "   / / ", "  /_/  ",
+        ],
+        'B' => [
+            " ____  ", "|  _ \\ ", "| |_) |", "|  _ < ", "| |_) |", "|____/ ",
+        ],
+        'C' => [
+            "  _____ ",
+            " / ____|",
+            "| |     ",
+            "| |     ",
+            "| |____ ",
+            " \\_____|",
+        ],
+        'D' => [
+            " _____  ",
+            "|  __ \\ ",
+            "| |  | |",
+            "| |  | |",
+            "| |__| |",
+            "|_____/ ",
+        ],
+        'F' => [
+            " ______ ", "|  ____|", "| |__   ", "|  __|  ", "| |     ", "|_|     ",
+        ],
+        'G' => [
+            "  _____ ",
+            " / ____|",
+            "| |  __ ",
+            "| | |_ |",
+            "| |__| |",
+            " \\_____|",
+        ],
+        'H' => [
+            " _    _ ", "| |  | |", "| |__| |", "|  __  |", "| |  | |", "|_|  |_|",
+        ],
+        'J' => [
+            "      _ ",
+            "     | |",
+            "     | |",
+            " _   | |",
+            "| |__| |",
+            " \\____/ ",
+        ],
+        'K' => [
+            " _  __", "| |/ /", "| ' / ", "|  <  ", "| . \\ ", "|_|\\_\\",
+        ],
+        'M' => [
+            " __  __ ",
+            "|  \\/  |",
+            "| \\  / |",
+            "| |\\/| |",
+            "| |  | |",
+            "|_|  |_|",
+        ],
+        'N' => [
+            " _   _ ", "| \\ | |", "|  \\| |", "| . ` |", "| |\\  |", "|_| \\_|",
+        ],
+        'P' => [
+            " _____  ",
+            "|  __ \\ ",
+            "| |__) |",
+            "|  ___/ ",
+            "| |     ",
+            "|_|     ",
+        ],
+        'Q' => [
+            "  ____  ",
+            " / __ \\ ",
+            "| |  | |",
+            "| |  | |",
+            "| |__| |",
+            " \\___\\_\\",
+        ],
+        'R' => [
+            " _____  ",
+            "|  __ \\ ",
+            "| |__) |",
+            "|  _  / ",
+            "| | \\ \\ ",
+            "|_|  \\_\\",
+        ],
+        'T' => [
+            " _______ ",
+            "|__   __|",
+            "   | |   ",
+            "   | |   ",
+            "   | |   ",
+            "   |_|   ",
+        ],
+        'V' => [
+            "__      __",
+            "\\ \\    / /",
+            " \\ \\  / / ",
+            "  \\ \\/ /  ",
+            "   \\  /   ",
+            "    \\/    ",
+        ],
+        'W' => [
+            "__          __",
+            "\\ \\        / /",
+            " \\ \\  /\\  / / ",
+            "  \\ \\/  \\/ /  ",
+            "   \\  /\\  /   ",
+            "    \\/  \\/    ",
+        ],
+        'X' => [
+            "__   __",
+            "\\ \\ / /",
+            " \\ V / ",
+            "  > <  ",
+            " / . \\ ",
+            "/_/ \\_\\",
+        ],
+        'Y' => [
+            "__     __",
+            "\\ \\   / /",
+            " \\ \\_/ / ",
+            "  \\   /  ",
+            "   | |   ",
+            "   |_|   ",
+        ],
+        _ => return None,
+    })
+}
+
+pub fn lite_font(input: &str) -> Vec<String> {
+    let mut output = vec![String::new(); 6];
+
+    for ch in input.chars() {
+        if let Some(rows) = glyph(ch) {
+            for (line, row) in output.iter_mut().zip(rows) {
+                line.push_str(row);
+            }
+        } else {
+            for line in &mut output {
+                line.push_str("       "); // space for unknown chars
+            }
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::totp::STEAM_ALPHABET;
+
+    #[test]
+    fn test_glyphs_are_rectangular() {
+        for ch in STEAM_ALPHABET.iter().map(|b| *b as char).chain('0'..='1') {
+            let rows = glyph(ch).unwrap_or_else(|| panic!("No glyph for {ch}"));
+            let width = rows[0].chars().count();
+            assert!(
+                rows.iter().all(|row| row.chars().count() == width),
+                "Ragged glyph for {ch}"
+            );
+        }
+        assert_eq!(lite_font("2B").len(), 6);
+    }
+}
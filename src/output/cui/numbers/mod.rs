@@ -1,2 +1,3 @@
+pub mod lite;
 pub mod utf8;
 pub mod pipe;
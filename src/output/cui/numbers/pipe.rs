@@ -1,24 +1,45 @@
+/// Rows of a single glyph; letters cover the Steam Guard alphabet
+fn glyph(ch: char) -> Option<[&'static str; 3]> {
+    Some(match ch.to_ascii_uppercase() {
+        '0' => ["╔═╗", "║ ║", "╚═╝"],
+        '1' => [" ║ ", " ║ ", " ║ "],
+        '2' => ["╔═╗", "╔═╝", "╚═╝"],
+        '3' => ["╔═╗", " ═╣", "╚═╝"],
+        '4' => ["║ ║", "╚═╣", "  ║"],
+        '5' => ["╔══", "╚═╗", "╚═╝"],
+        '6' => ["╔══", "╠═╗", "╚═╝"],
+        '7' => ["══╗", "  ║", "  ║"],
+        '8' => ["╔═╗", "╠═╣", "╚═╝"],
+        '9' => ["╔═╗", "╚═╣", "══╝"],
+        'B' => ["╦═╗", "╠═╣", "╩═╝"],
+        'C' => ["╔══", "║  ", "╚══"],
+        'D' => ["╦═╗", "║ ║", "╩═╝"],
+        'F' => ["╔══", "╠═ ", "║  "],
+        'G' => ["╔══", "║ ╗", "╚═╝"],
+        'H' => ["║ ║", "╠═╣", "║ ║"],
+        'J' => ["  ║", "  ║", "╚═╝"],
+        'K' => ["║ ╱", "╠═ ", "║ ╲"],
+        'M' => ["╔╦╗", "║║║", "║ ║"],
+        'N' => ["╔╗║", "║║║", "║╚╝"],
+        'P' => ["╔═╗", "╠═╝", "║  "],
+        'Q' => ["╔═╗", "║ ║", "╚═╬"],
+        'R' => ["╔═╗", "╠╦╝", "║╚╗"],
+        'T' => ["═╦═", " ║ ", " ║ "],
+        'V' => ["║ ║", "╲ ╱", " ╨ "],
+        'W' => ["║ ║", "║║║", "╚╩╝"],
+        'X' => ["╲ ╱", " ╳ ", "╱ ╲"],
+        'Y' => ["╲ ╱", " ║ ", " ║ "],
+        _ => return None,
+    })
+}
 
 pub fn big_number_font(input: &str) -> Vec<String> {
-    let digits = [
-        ["╔═╗", "║ ║", "╚═╝"], // 0
-        [" ║ ", " ║ ", " ║ "], // 1
-        ["╔═╗", "╔═╝", "╚═╝"], // 2
-        ["╔═╗", " ═╣", "╚═╝"], // 3
-        ["║ ║", "╚═╣", "  ║"], // 4
-        ["╔══", "╚═╗", "╚═╝"], // 5
-        ["╔══", "╠═╗", "╚═╝"], // 6
-        ["══╗", "  ║", "  ║"], // 7
-        ["╔═╗", "╠═╣", "╚═╝"], // 8
-        ["╔═╗", "╚═╣", "══╝"], // 9
-    ];
-
     let mut lines = vec![String::new(), String::new(), String::new()];
 
     for ch in input.chars() {
-        if let Some(d) = ch.to_digit(10) {
-            for (line, ch) in lines.iter_mut().zip(digits[d as usize]) {
-                line.push_str(ch);
+        if let Some(rows) = glyph(ch) {
+            for (line, row) in lines.iter_mut().zip(rows) {
+                line.push_str(row);
             }
         } else {
             for line in lines.iter_mut() {
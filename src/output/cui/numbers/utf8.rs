@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 
+/// Letters cover the Steam Guard alphabet
 pub fn utf8_font(input: &str) -> Vec<String> {
     let font: HashMap<char, [&str; 5]> = HashMap::from([
         ('0', [
@@ -72,10 +73,136 @@ pub fn utf8_font(input: &str) -> Vec<String> {
          "    ██ ",
          "  ███  ",
         ]),
+        ('B', [
+         " ████  ",
+         " ██ ██ ",
+         " ████  ",
+         " ██ ██ ",
+         " ████  ",
+        ]),
+        ('C', [
+         "  ████ ",
+         " ██    ",
+         " ██    ",
+         " ██    ",
+         "  ████ ",
+        ]),
+        ('D', [
+         " ████  ",
+         " ██ ██ ",
+         " ██ ██ ",
+         " ██ ██ ",
+         " ████  ",
+        ]),
+        ('F', [
+         " █████ ",
+         " ██    ",
+         " ████  ",
+         " ██    ",
+         " ██    ",
+        ]),
+        ('G', [
+         "  ████ ",
+         " ██    ",
+         " ██ ██ ",
+         " ██ ██ ",
+         "  ████ ",
+        ]),
+        ('H', [
+         " ██ ██ ",
+         " ██ ██ ",
+         " █████ ",
+         " ██ ██ ",
+         " ██ ██ ",
+        ]),
+        ('J', [
+         "    ██ ",
+         "    ██ ",
+         "    ██ ",
+         " ██ ██ ",
+         "  ███  ",
+        ]),
+        ('K', [
+         " ██ ██ ",
+         " ████  ",
+         " ███   ",
+         " ████  ",
+         " ██ ██ ",
+        ]),
+        ('M', [
+         " ██   ██ ",
+         " ███ ███ ",
+         " ██ █ ██ ",
+         " ██   ██ ",
+         " ██   ██ ",
+        ]),
+        ('N', [
+         " ██  ██ ",
+         " ███ ██ ",
+         " ██████ ",
+         " ██ ███ ",
+         " ██  ██ ",
+        ]),
+        ('P', [
+         " ████  ",
+         " ██ ██ ",
+         " ████  ",
+         " ██    ",
+         " ██    ",
+        ]),
+        ('Q', [
+         "  ███  ",
+         " ██ ██ ",
+         " ██ ██ ",
+         "  ███  ",
+         "    ██ ",
+        ]),
+        ('R', [
+         " ████  ",
+         " ██ ██ ",
+         " ████  ",
+         " ██ ██ ",
+         " ██ ██ ",
+        ]),
+        ('T', [
+         " ██████ ",
+         "   ██   ",
+         "   ██   ",
+         "   ██   ",
+         "   ██   ",
+        ]),
+        ('V', [
+         " ██ ██ ",
+         " ██ ██ ",
+         " ██ ██ ",
+         "  ███  ",
+         "   █   ",
+        ]),
+        ('W', [
+         " ██   ██ ",
+         " ██   ██ ",
+         " ██ █ ██ ",
+         " ███ ███ ",
+         " ██   ██ ",
+        ]),
+        ('X', [
+         " ██ ██ ",
+         "  ███  ",
+         "   █   ",
+         "  ███  ",
+         " ██ ██ ",
+        ]),
+        ('Y', [
+         " ██ ██ ",
+         " ██ ██ ",
+         "  ███  ",
+         "   █   ",
+         "   █   ",
+        ]),
         ]);
     let mut output = vec![String::new(); 5];
     for ch in input.chars() {
-        if let Some(rows) = font.get(&ch) {
+        if let Some(rows) = font.get(&ch.to_ascii_uppercase()) {
             for (i, row) in rows.iter().enumerate() {
                 output[i].push_str(row);
                 output[i].push(' ');
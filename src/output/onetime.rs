@@ -1,14 +1,89 @@
-use crate::config::secrets::ConfigFile;
-use crate::totp::Totp;
+use anyhow::bail;
 
-pub async fn one_time_mode(cf: &mut ConfigFile, arg: &str) -> anyhow::Result<String> {
+use crate::clock::Clock;
+use crate::config::{secrets::ConfigFile, sources::ConfigSources};
+
+pub async fn one_time_mode(
+    cf: &ConfigSources,
+    arg: &str,
+    tag: Option<&str>,
+    advance: bool,
+    next: bool,
+    clock: &dyn Clock,
+) -> anyhow::Result<String> {
     if arg.is_empty() {
         tracing::warn!("No argument provided for one-time mode; this is meant to be the code/index of the secret. This could lead to unexpected behavior.");
     }
     let (_, secrets) = cf.load().await?;
     tracing::debug!("{} secrets have been loaded in one time mode ", secrets.len());
-    let totp = ConfigFile::get_secret(&secrets, arg)
-        .map(|entry| Totp::new(&entry.secret, entry.timestep, entry.digits))?;
-    let valid_in_seconds = totp.valid_duration();
-    Ok(format!("{}\nValid for {}s", totp.token, valid_in_seconds))
+    // A typo must not advance the counter of another entry
+    let find = if advance {
+        ConfigFile::get_exact_index
+    } else {
+        ConfigFile::get_index
+    };
+    let index = match tag {
+        Some(tag) => {
+            // Looked up among the tagged entries, then mapped back to the merged list
+            let (indexes, tagged): (Vec<usize>, Vec<_>) = secrets
+                .iter()
+                .cloned()
+                .enumerate()
+                .filter(|(_, entry)| entry.has_tag(tag))
+                .unzip();
+            if tagged.is_empty() {
+                bail!("No entry is tagged {tag}");
+            }
+            indexes[find(&tagged, arg)?]
+        }
+        None => find(&secrets, arg)?,
+    };
+    let entry = if advance {
+        cf.advance_counter(index, &secrets[index]).await?
+    } else {
+        secrets[index].clone()
+    };
+    let totp = entry.totp(clock)?;
+    match entry.generator()?.time_step(&entry) {
+        Some(_) => {
+            let valid_in_seconds = totp.valid_duration(clock);
+            let mut output = format!("{}\nValid for {}s", totp.token, valid_in_seconds);
+            if next && let Some(next_token) = &totp.next_token {
+                output.push_str(&format!("\nNext {next_token}"));
+            }
+            Ok(output)
+        }
+        None => Ok(format!("{}\nCounter {}", totp.token, totp.counter())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::FakeClock;
+
+    #[tokio::test]
+    async fn test_next_code() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("secrets.json");
+        std::fs::write(
+            &path,
+            r#"[
+                {"name": "Mail", "handle": "mail", "secret": "JBSWY3DPEHPK3PXP"},
+                {"name": "Bank", "handle": "bank", "secret": "JBSWY3DPEHPK3PXP", "type": "hotp"}
+            ]"#,
+        )
+        .unwrap();
+        let sources = ConfigSources::single(ConfigFile::new(path.to_string_lossy().into_owned()));
+        let clock = FakeClock::at(1748742637);
+        let run = |target, next| one_time_mode(&sources, target, None, false, next, &clock);
+
+        assert_eq!(run("mail", false).await.unwrap(), "879599\nValid for 23s");
+        assert_eq!(
+            run("mail", true).await.unwrap(),
+            "879599\nValid for 23s\nNext 690726"
+        );
+        // Counter based entries have no next code
+        assert_eq!(run("bank", true).await.unwrap(), "282760\nCounter 0");
+    }
 }
@@ -1,29 +1,57 @@
 use crate::{
-    config::secrets::{ConfigEntryPublic, ConfigFile},
-    totp::Totp,
+    clock::Clock,
+    config::{
+        lookup::{self, LookupError},
+        secrets::{ConfigEntry, ConfigEntryPublic},
+        sources::ConfigSources,
+    },
+    skew::ClockSkew,
+    totp::{Totp, TotpError},
+    verify::Verifier,
 };
 use actix_web::{
-    App, HttpResponse, HttpResponseBuilder, HttpServer, Responder, get,
+    App, HttpResponse, HttpResponseBuilder, HttpServer, Responder, get, post,
     http::header::{self, Accept, ContentType},
     mime, web,
 };
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
 #[cfg(feature = "http")]
 #[get("/list")]
-async fn list_entries(secrets_cf: web::Data<Arc<ConfigFile>>) -> impl Responder {
+async fn list_entries(
+    secrets_cf: web::Data<Arc<ConfigSources>>,
+    clock_skew: web::Data<Arc<ClockSkew>>,
+    query: web::Query<ListQuery>,
+) -> impl Responder {
     let result: anyhow::Result<String> = async {
         let (_, secrets) = secrets_cf.load().await?;
         // Convert secrets to their public representation
-        let secrets: Vec<ConfigEntryPublic> = secrets.iter().map(|entry| entry.into()).collect();
-        let as_string = serde_json::to_string(&secrets)?;
+        let entries: Vec<ConfigEntryPublic> = secrets
+            .iter()
+            .filter(|entry| query.tag.as_deref().is_none_or(|tag| entry.has_tag(tag)))
+            .map(|entry| entry.into())
+            .collect();
+        let as_string = if query.clock_skew {
+            serde_json::to_string(&ListWithSkew {
+                entries,
+                clock_skew: clock_skew.get(),
+            })?
+        } else {
+            serde_json::to_string(&entries)?
+        };
         Ok(as_string)
     }
     .await;
     match result {
-        Ok(secrets) => HttpResponse::Ok()
-            .content_type(ContentType::json())
-            .body(secrets),
+        Ok(secrets) => {
+            let mut builder = HttpResponse::Ok();
+            // Also sent as a header, for clients reading the plain list of entries
+            if let Some(skew) = clock_skew.get() {
+                builder.insert_header((CLOCK_SKEW_HEADER, skew.to_string()));
+            }
+            builder.content_type(ContentType::json()).body(secrets)
+        }
         Err(err) => {
             tracing::error!("Failed to load secrets: {}", err);
             HttpResponse::BadRequest().body("Failed to load secrets")
@@ -31,6 +59,25 @@ async fn list_entries(secrets_cf: web::Data<Arc<ConfigFile>>) -> impl Responder
     }
 }
 
+#[derive(Deserialize)]
+struct ListQuery {
+    /// Only list the entries with this tag
+    tag: Option<String>,
+    /// Wrap the entries in an object along with the clock skew
+    #[serde(default)]
+    clock_skew: bool,
+}
+
+#[derive(Serialize)]
+struct ListWithSkew<'a> {
+    entries: Vec<ConfigEntryPublic<'a>>,
+    /// `None` until the clock has been checked, see [`CLOCK_SKEW_HEADER`]
+    clock_skew: Option<i64>,
+}
+
+/// Seconds the local clock is behind the configured time source, only sent once checked
+const CLOCK_SKEW_HEADER: &str = "X-Clock-Skew";
+
 fn plain_text_response(mut resp: HttpResponseBuilder, totp: Totp) -> HttpResponse {
     resp.content_type("text/plain")
         .body(format!("{}\n", totp.token))
@@ -43,58 +90,284 @@ fn accept_contains_json(accept: &Accept) -> bool {
     })
 }
 
+/// Looks up an entry with `find`, i.e. [`lookup::find`] to display it and
+/// [`lookup::find_exact`] to advance or verify it, or builds the error response to send back
+async fn find_entry(
+    secrets_cf: &ConfigSources,
+    code: &str,
+    find: fn(&[ConfigEntry], &str) -> Result<usize, LookupError>,
+) -> Result<(usize, ConfigEntry), HttpResponse> {
+    let (_, secrets) = secrets_cf.load().await.map_err(|err| {
+        tracing::error!("Failed to load secrets: {}", err);
+        HttpResponse::BadRequest().body("Failed to load secrets")
+    })?;
+    let index = find(&secrets, code).map_err(|err| match err {
+        LookupError::NotFound(_) => HttpResponse::NotFound().body("No matching code found."),
+        LookupError::Ambiguous { .. } => HttpResponse::Conflict().body(err.to_string()),
+    })?;
+    Ok((index, secrets[index].clone()))
+}
+
+#[derive(Serialize)]
+struct InvalidEntry<'a> {
+    error: &'static str,
+    message: String,
+    name: &'a str,
+}
+
+/// Entries that cannot produce a token are reported as 422 rather than taking the worker down
+fn invalid_entry_response(entry: &ConfigEntry, err: &TotpError) -> HttpResponse {
+    tracing::warn!("Entry {} is invalid: {}", entry.name, err);
+    HttpResponse::UnprocessableEntity().json(InvalidEntry {
+        error: err.kind(),
+        message: err.to_string(),
+        name: &entry.name,
+    })
+}
+
 #[cfg(feature = "http")]
 #[get("/code/{code}")]
 async fn get_code(
-    secrets_cf: web::Data<Arc<ConfigFile>>,
+    secrets_cf: web::Data<Arc<ConfigSources>>,
+    clock: web::Data<Arc<dyn Clock>>,
     path: web::Path<String>,
     accept: Option<web::Header<header::Accept>>,
 ) -> impl Responder {
     let code = path.into_inner();
 
-    let result: anyhow::Result<Totp> = async {
-        let (_, secrets) = secrets_cf.load().await?;
-        let entry = ConfigFile::get_secret(&secrets, code.as_str())?;
-        let totp = Totp::new(entry.secret.as_str(), entry.timestep, entry.digits);
-        Ok(totp)
+    let entry = match find_entry(&secrets_cf, code.as_str(), lookup::find).await {
+        Ok((_, entry)) => entry,
+        Err(response) => return response,
+    };
+    match entry.totp(clock.get_ref().as_ref()) {
+        Ok(totp) => totp_response(totp, accept),
+        Err(err) => invalid_entry_response(&entry, &err),
     }
-    .await;
+}
 
-    match result {
-        Ok(totp) => {
-            let mut builder = HttpResponse::Ok();
-            match accept {
-                Some(header) => {
-                    let header = header.into_inner();
-                    if accept_contains_json(&header) {
-                        builder.json(totp)
-                    } else {
-                        plain_text_response(builder, totp)
-                    }
-                }
-                _ => plain_text_response(builder, totp),
+/// Increments the counter of a HOTP entry and returns the code for the new counter
+#[cfg(feature = "http")]
+#[post("/code/{code}/advance")]
+async fn advance_code(
+    secrets_cf: web::Data<Arc<ConfigSources>>,
+    clock: web::Data<Arc<dyn Clock>>,
+    path: web::Path<String>,
+    accept: Option<web::Header<header::Accept>>,
+) -> impl Responder {
+    let code = path.into_inner();
+
+    let (index, entry) = match find_entry(&secrets_cf, code.as_str(), lookup::find_exact).await {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+    if let Err(err) = entry.validate() {
+        return invalid_entry_response(&entry, &err);
+    }
+    match secrets_cf.advance_counter(index, &entry).await {
+        Ok(entry) => match entry.totp(clock.get_ref().as_ref()) {
+            Ok(totp) => totp_response(totp, accept),
+            Err(err) => invalid_entry_response(&entry, &err),
+        },
+        Err(err) => {
+            tracing::warn!("Failed to advance counter: {}", err);
+            HttpResponse::BadRequest().body(format!("{err}"))
+        }
+    }
+}
+
+/// Checks the code sent as the request body against the entry; a given code is only accepted once
+#[cfg(feature = "http")]
+#[post("/verify/{code}")]
+async fn verify_code(
+    secrets_cf: web::Data<Arc<ConfigSources>>,
+    verifier: web::Data<Arc<Verifier>>,
+    path: web::Path<String>,
+    body: String,
+) -> impl Responder {
+    let code = path.into_inner();
+
+    let (index, entry) = match find_entry(&secrets_cf, code.as_str(), lookup::find_exact).await {
+        Ok(found) => found,
+        Err(response) => return response,
+    };
+    match secrets_cf.verify(index, &entry, &body, &verifier).await {
+        Ok(outcome) if outcome.is_accepted() => HttpResponse::Ok().json(outcome),
+        Ok(outcome) => HttpResponse::Unauthorized().json(outcome),
+        Err(err) => match err.downcast_ref::<TotpError>() {
+            Some(err) => invalid_entry_response(&entry, err),
+            None => HttpResponse::BadRequest().body(format!("{err}")),
+        },
+    }
+}
+
+fn totp_response(totp: Totp, accept: Option<web::Header<header::Accept>>) -> HttpResponse {
+    let mut builder = HttpResponse::Ok();
+    match accept {
+        Some(header) => {
+            let header = header.into_inner();
+            if accept_contains_json(&header) {
+                builder.json(totp)
+            } else {
+                plain_text_response(builder, totp)
             }
         }
-        Err(_) => HttpResponse::NotFound().body("No matching code found."),
+        _ => plain_text_response(builder, totp),
     }
 }
 
+fn routes(config: &mut web::ServiceConfig) {
+    config
+        .service(list_entries)
+        .service(get_code)
+        .service(advance_code)
+        .service(verify_code);
+}
+
 // Function to launch the server
 #[cfg(feature = "http")]
 pub async fn start_server(
     bind: String,
     port: u16,
-    secrets_cf: Arc<ConfigFile>,
+    secrets_cf: Arc<ConfigSources>,
+    verifier: Arc<Verifier>,
+    clock: Arc<dyn Clock>,
+    clock_skew: Arc<ClockSkew>,
 ) -> anyhow::Result<()> {
-    tracing::debug!("Secrets will be read from {}", secrets_cf.secrets_path);
+    tracing::debug!("Secrets will be read from {}", secrets_cf.describe());
     HttpServer::new(move || {
         App::new()
             .app_data(web::Data::new(Arc::clone(&secrets_cf)))
-            .service(list_entries)
-            .service(get_code)
+            .app_data(web::Data::new(Arc::clone(&verifier)))
+            .app_data(web::Data::new(Arc::clone(&clock)))
+            .app_data(web::Data::new(Arc::clone(&clock_skew)))
+            .configure(routes)
     })
     .bind((bind, port))?
     .run()
     .await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        body::MessageBody,
+        dev::{ServiceFactory, ServiceRequest, ServiceResponse},
+        test,
+    };
+
+    use super::*;
+    use crate::{clock::FakeClock, config::secrets::ConfigFile};
+
+    /// The API over the secrets file `path` holding `entries`, at a fixed time
+    fn api(
+        path: std::path::PathBuf,
+        entries: &str,
+    ) -> App<
+        impl ServiceFactory<
+            ServiceRequest,
+            Config = (),
+            Response = ServiceResponse<impl MessageBody>,
+            Error = actix_web::Error,
+            InitError = (),
+        >,
+    > {
+        std::fs::write(&path, entries).unwrap();
+        let secrets_cf =
+            ConfigSources::single(ConfigFile::new(path.to_string_lossy().into_owned()));
+        let clock: Arc<dyn Clock> = Arc::new(FakeClock::at(1748742637));
+        let verifier = Verifier::new(1, Arc::clone(&clock));
+        App::new()
+            .app_data(web::Data::new(Arc::new(secrets_cf)))
+            .app_data(web::Data::new(Arc::new(verifier)))
+            .app_data(web::Data::new(clock))
+            .app_data(web::Data::new(Arc::new(ClockSkew::new(5))))
+            .configure(routes)
+    }
+
+    #[actix_web::test]
+    async fn test_list_with_clock_skew() {
+        let directory = tempfile::tempdir().unwrap();
+        let entries = r#"[{"name": "Mail", "secret": "JBSWY3DPEHPK3PXP"}]"#;
+        let api = test::init_service(api(directory.path().join("secrets.json"), entries)).await;
+
+        let request = test::TestRequest::get().uri("/list").to_request();
+        let list: serde_json::Value = test::call_and_read_body_json(&api, request).await;
+        assert_eq!(list[0]["name"], "Mail");
+
+        let request = test::TestRequest::get()
+            .uri("/list?clock_skew=true")
+            .to_request();
+        let list: serde_json::Value = test::call_and_read_body_json(&api, request).await;
+        assert_eq!(list["entries"][0]["name"], "Mail");
+        assert_eq!(list["clock_skew"], serde_json::Value::Null);
+    }
+
+    #[actix_web::test]
+    async fn test_entries_by_tag_and_lookup() {
+        let directory = tempfile::tempdir().unwrap();
+        let entries = r#"[
+            {"name": "Mail work", "handle": "work", "secret": "JBSWY3DPEHPK3PXP", "tags": ["Job"]},
+            {"name": "Mail home", "handle": "home", "secret": "JBSWY3DPEHPK3PXP"}
+        ]"#;
+        let api = test::init_service(api(directory.path().join("secrets.json"), entries)).await;
+
+        let request = test::TestRequest::get().uri("/list?tag=job").to_request();
+        let list: serde_json::Value = test::call_and_read_body_json(&api, request).await;
+        assert_eq!(list.as_array().unwrap().len(), 1);
+        assert_eq!(list[0]["code"], "work");
+
+        let request = test::TestRequest::get().uri("/code/mail").to_request();
+        let response = test::call_service(&api, request).await;
+        assert_eq!(response.status(), 409);
+        let request = test::TestRequest::get().uri("/code/home").to_request();
+        assert_eq!(test::call_and_read_body(&api, request).await, "879599\n");
+    }
+
+    #[actix_web::test]
+    async fn test_invalid_entry() {
+        let directory = tempfile::tempdir().unwrap();
+        let entries = r#"[{"name": "Broken", "handle": "broken", "secret": "not base32!"}]"#;
+        let api = test::init_service(api(directory.path().join("secrets.json"), entries)).await;
+
+        let request = test::TestRequest::get().uri("/code/broken").to_request();
+        let response = test::call_service(&api, request).await;
+        assert_eq!(response.status(), 422);
+        let body: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "error": "malformed_secret",
+                "message": "Secret is not valid base32",
+                "name": "Broken",
+            })
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_verify() {
+        let directory = tempfile::tempdir().unwrap();
+        let entries = r#"[{"name": "Mail", "handle": "mail", "secret": "JBSWY3DPEHPK3PXP"}]"#;
+        let api = test::init_service(api(directory.path().join("secrets.json"), entries)).await;
+        let verify = |code: &str| {
+            test::TestRequest::post()
+                .uri("/verify/mail")
+                .set_payload(code.to_owned())
+                .to_request()
+        };
+
+        let response = test::call_service(&api, verify("879599")).await;
+        assert_eq!(response.status(), 200);
+        let body: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(
+            body,
+            serde_json::json!({"outcome": "accepted", "counter": 58291421, "drift": 0})
+        );
+        for (code, outcome) in [("879599", "replayed"), ("000000", "rejected")] {
+            let response = test::call_service(&api, verify(code)).await;
+            assert_eq!(response.status(), 401);
+            let body: serde_json::Value = test::read_body_json(response).await;
+            assert_eq!(body["outcome"], outcome);
+        }
+    }
+}
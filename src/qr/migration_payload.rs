@@ -1,7 +1,15 @@
+//! Entries of a Google Authenticator export. Decoding the protobuf needs the `configure` feature,
+//! mapping the decoded entries does not
+
+use anyhow::{anyhow, bail};
 use base32::{Alphabet, encode};
 
-use crate::config::secrets::ConfigEntry;
+use crate::{
+    config::{otpauth, secrets::ConfigEntry},
+    totp::{Algorithm, OtpType},
+};
 
+#[cfg(feature = "configure")]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct MigrationPayload {
     #[prost(message, repeated, tag = "1")]
@@ -16,27 +24,152 @@ pub struct MigrationPayload {
     pub batch_id: i32,
 }
 
-#[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "configure", derive(::prost::Message))]
+#[cfg_attr(not(feature = "configure"), derive(Debug, Default))]
 pub struct OtpParameters {
-    #[prost(bytes = "vec", tag = "1")]
-    pub secret: ::prost::alloc::vec::Vec<u8>,
-    #[prost(string, tag = "2")]
-    pub name: ::prost::alloc::string::String,
-    #[prost(string, tag = "3")]
-    pub issuer: ::prost::alloc::string::String,
-    #[prost(int32, tag = "4")]
+    #[cfg_attr(feature = "configure", prost(bytes = "vec", tag = "1"))]
+    pub secret: Vec<u8>,
+    #[cfg_attr(feature = "configure", prost(string, tag = "2"))]
+    pub name: String,
+    #[cfg_attr(feature = "configure", prost(string, tag = "3"))]
+    pub issuer: String,
+    #[cfg_attr(feature = "configure", prost(int32, tag = "4"))]
     pub algorithm: i32,
-    #[prost(int32, tag = "5")]
+    #[cfg_attr(feature = "configure", prost(int32, tag = "5"))]
     pub digits: i32,
-    #[prost(int32, tag = "6")]
+    #[cfg_attr(feature = "configure", prost(int32, tag = "6"))]
     pub r#type: i32,
-    #[prost(int32, tag = "7")]
-    pub counter: i32,
+    #[cfg_attr(feature = "configure", prost(int64, tag = "7"))]
+    pub counter: i64,
 }
 
-impl From<OtpParameters> for ConfigEntry {
-    fn from(param: OtpParameters) -> Self {
+// Enum values as defined by Google Authenticator's migration protobuf
+const ALGORITHM_UNSPECIFIED: i32 = 0;
+const ALGORITHM_SHA1: i32 = 1;
+const ALGORITHM_SHA256: i32 = 2;
+const ALGORITHM_SHA512: i32 = 3;
+const ALGORITHM_MD5: i32 = 4;
+const DIGITS_UNSPECIFIED: i32 = 0;
+const DIGITS_SIX: i32 = 1;
+const DIGITS_EIGHT: i32 = 2;
+const TYPE_UNSPECIFIED: i32 = 0;
+const TYPE_HOTP: i32 = 1;
+const TYPE_TOTP: i32 = 2;
+
+impl TryFrom<OtpParameters> for ConfigEntry {
+    type Error = anyhow::Error;
+
+    /// Fails when the exported entry relies on something the generator cannot reproduce, since
+    /// importing it anyway would silently produce wrong codes. The name is split like the label
+    /// of an otpauth:// URI, see [`otpauth::parse`]
+    fn try_from(param: OtpParameters) -> anyhow::Result<Self> {
+        let algorithm = match param.algorithm {
+            ALGORITHM_SHA1 => Algorithm::Sha1,
+            ALGORITHM_SHA256 => Algorithm::Sha256,
+            ALGORITHM_SHA512 => Algorithm::Sha512,
+            ALGORITHM_UNSPECIFIED => {
+                tracing::warn!("No algorithm specified for {}, assuming SHA1", param.name);
+                Algorithm::Sha1
+            }
+            ALGORITHM_MD5 => bail!("{} uses the MD5 algorithm which is not supported", param.name),
+            other => bail!("{} uses an unknown algorithm ({other})", param.name),
+        };
+        let digits = match param.digits {
+            DIGITS_SIX => 6,
+            DIGITS_EIGHT => 8,
+            DIGITS_UNSPECIFIED => {
+                tracing::warn!("No digits specified for {}, assuming 6", param.name);
+                6
+            }
+            other => bail!("{} uses an unknown number of digits ({other})", param.name),
+        };
+        let otp_type = match param.r#type {
+            TYPE_TOTP => OtpType::Totp,
+            TYPE_HOTP => OtpType::Hotp,
+            TYPE_UNSPECIFIED => {
+                tracing::warn!("No type specified for {}, assuming TOTP", param.name);
+                OtpType::Totp
+            }
+            other => bail!("{} uses an unknown OTP type ({other})", param.name),
+        };
+        let counter = match u64::try_from(param.counter) {
+            Ok(counter) => counter,
+            Err(_) => bail!("{} has a negative counter ({})", param.name, param.counter),
+        };
+
+        let (label_issuer, account) = otpauth::split_label(&param.name);
+        let issuer = Some(param.issuer)
+            .filter(|issuer| !issuer.is_empty())
+            .or(label_issuer)
+            .filter(|issuer| !issuer.is_empty());
+        let account = Some(account).filter(|account| !account.is_empty());
+        let name = issuer
+            .clone()
+            .or(account.clone())
+            .ok_or(anyhow!("An exported entry has neither an issuer nor a name"))?;
+
         let secret = encode(Alphabet::RFC4648 { padding: false }, &param.secret);
-        ConfigEntry::new(param.name, secret)
+        let mut entry = ConfigEntry::new(name, secret);
+        entry.issuer = issuer;
+        entry.account = account;
+        entry.algorithm = algorithm;
+        entry.digits = digits;
+        entry.otp_type = otp_type;
+        entry.counter = counter;
+        Ok(entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parameters(algorithm: i32, digits: i32, r#type: i32) -> OtpParameters {
+        OtpParameters {
+            secret: b"Hello!".to_vec(),
+            name: "Test1:test1@example1.com".to_owned(),
+            issuer: "Test1".to_owned(),
+            algorithm,
+            digits,
+            r#type,
+            counter: 7,
+        }
+    }
+
+    #[test]
+    fn test_maps_every_field() {
+        let entry =
+            ConfigEntry::try_from(parameters(ALGORITHM_SHA256, DIGITS_EIGHT, TYPE_HOTP)).unwrap();
+        assert_eq!(entry.name, "Test1");
+        assert_eq!(entry.issuer.as_deref(), Some("Test1"));
+        assert_eq!(entry.account.as_deref(), Some("test1@example1.com"));
+        assert_eq!(entry.secret.expose(), "JBSWY3DPEE");
+        assert_eq!(entry.algorithm, Algorithm::Sha256);
+        assert_eq!(entry.digits, 8);
+        assert_eq!(entry.otp_type, OtpType::Hotp);
+        assert_eq!(entry.counter, 7);
+    }
+
+    #[test]
+    fn test_names_without_issuer() {
+        let mut exported = parameters(ALGORITHM_SHA1, DIGITS_SIX, TYPE_TOTP);
+        exported.issuer.clear();
+        exported.name = "alice".to_owned();
+        let entry = ConfigEntry::try_from(exported.clone()).unwrap();
+        assert_eq!((entry.name.as_str(), entry.issuer), ("alice", None));
+
+        // The issuer may only be given by the name
+        exported.name = "GitHub: alice".to_owned();
+        let entry = ConfigEntry::try_from(exported).unwrap();
+        assert_eq!(entry.issuer.as_deref(), Some("GitHub"));
+        assert_eq!(entry.account.as_deref(), Some("alice"));
+    }
+
+    #[test]
+    fn test_refuses_unsupported_features() {
+        assert!(ConfigEntry::try_from(parameters(ALGORITHM_MD5, DIGITS_SIX, TYPE_TOTP)).is_err());
+        assert!(ConfigEntry::try_from(parameters(ALGORITHM_SHA1, 3, TYPE_TOTP)).is_err());
+        assert!(ConfigEntry::try_from(parameters(ALGORITHM_SHA1, DIGITS_SIX, 3)).is_err());
     }
 }
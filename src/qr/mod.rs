@@ -1,4 +1,3 @@
-#[cfg(feature = "configure")]
 pub mod migration_payload;
 #[cfg(feature = "configure")]
 pub mod reader;
@@ -18,7 +18,14 @@ pub async fn generate_configuration(
         .into_iter()
         .filter_map(|e| {
             let name = e.name.clone();
-            let mut entry = ConfigEntry::from(e);
+            let mut entry = match ConfigEntry::try_from(e) {
+                Ok(entry) => entry,
+                Err(err) => {
+                    tracing::warn!("Skipping entry: {err}");
+                    eprintln!("Skipping {name}: {err}");
+                    return None;
+                }
+            };
             if prompt {
                 print!(
                     "Enter code for {} (or `-` to not include into config): ",
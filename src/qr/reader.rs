@@ -15,6 +15,7 @@ use url::Url;
 
 use super::{migration_payload::{MigrationPayload, OtpParameters}, zbar};
 
+/// Reads QR codes from images and decodes Google Authenticator exports
 pub struct QrDecoder;
 // Example URI:
 // otpauth-migration://offline?data=CjMKCkhlbGxvId6tvu8SGFRlc3QxOnRlc3QxQGV4YW1wbGUxLmNvbRoFVGVzdDEgASgBMAIKMwoKSGVsbG8h3q2%2B8BIYVGVzdDI6dGVzdDJAZXhhbXBsZTIuY29tGgVUZXN0MiABKAEwAgozCgpIZWxsbyHerb7xEhhUZXN0Mzp0ZXN0M0BleGFtcGxlMy5jb20aBVRlc3QzIAEoATACEAEYASAAKI3orYEE
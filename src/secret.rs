@@ -0,0 +1,76 @@
+//! Secret material that stays out of logs and is wiped from memory once dropped
+
+use std::fmt;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+/// Wraps a secret so that `Debug` never prints it and its memory is zeroed on drop
+///
+/// The value can only be read through [`Secret::expose`], which keeps every use easy to find.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Secret<T: Zeroize>(T);
+
+impl<T: Zeroize> Secret<T> {
+    pub fn new(value: T) -> Self {
+        Secret(value)
+    }
+
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Zeroize> From<T> for Secret<T> {
+    fn from(value: T) -> Self {
+        Secret(value)
+    }
+}
+
+impl<T: Zeroize> Drop for Secret<T> {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl<T: Zeroize> ZeroizeOnDrop for Secret<T> {}
+
+impl<T: Zeroize> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[redacted]")
+    }
+}
+
+/// Serialized as is, since the secrets file is where secrets are meant to be written
+impl<T: Zeroize + Serialize> Serialize for Secret<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de, T: Zeroize + Deserialize<'de>> Deserialize<'de> for Secret<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(Secret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::secrets::ConfigEntry;
+
+    #[test]
+    fn test_secrets_are_redacted() {
+        let entry: ConfigEntry = serde_json::from_str(
+            r#"{"name": "Test", "secret": "JBSWY3DPEHPK3PXP", "pin": "9876"}"#,
+        )
+        .unwrap();
+        let debug = format!("{entry:?}");
+        assert!(!debug.contains("JBSWY3DPEHPK3PXP"), "{debug}");
+        assert!(!debug.contains("9876"), "{debug}");
+        assert_eq!(format!("{:?}", entry.key().unwrap()), "[redacted]");
+
+        // Writing the entry back still produces the secret
+        let json = serde_json::to_string(&entry).unwrap();
+        assert!(json.contains(r#""secret":"JBSWY3DPEHPK3PXP""#), "{json}");
+    }
+}
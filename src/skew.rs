@@ -0,0 +1,259 @@
+use std::{
+    fmt,
+    str::FromStr,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpStream, UdpSocket},
+    time,
+};
+
+use crate::clock::Clock;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// Seconds between the NTP epoch (1900) and the UNIX epoch (1970)
+const NTP_UNIX_OFFSET: f64 = 2_208_988_800.0;
+
+/// Reference time used to detect a wrong local clock
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TimeSource {
+    /// SNTP server, as `host:port`
+    Sntp(String),
+    /// Plain HTTP server whose `Date` header is used, as `host:port` and path
+    Http(String, String),
+}
+
+impl FromStr for TimeSource {
+    type Err = anyhow::Error;
+
+    /// Accepts `sntp://host[:port]` (or `ntp://`) and `http://host[:port][/path]`
+    fn from_str(s: &str) -> Result<Self> {
+        let (scheme, rest) = s
+            .split_once("://")
+            .ok_or(anyhow!("Expected sntp://host or http://host, got {s}"))?;
+        let (host, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        if host.is_empty() {
+            bail!("Missing host in {s}");
+        }
+        let with_port = |port: u16| {
+            if host.contains(':') {
+                host.to_owned()
+            } else {
+                format!("{host}:{port}")
+            }
+        };
+        match scheme {
+            "sntp" | "ntp" => Ok(TimeSource::Sntp(with_port(123))),
+            "http" => Ok(TimeSource::Http(with_port(80), path.to_owned())),
+            other => bail!("Unsupported time source scheme {other}"),
+        }
+    }
+}
+
+impl fmt::Display for TimeSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TimeSource::Sntp(host) => write!(f, "sntp://{host}"),
+            TimeSource::Http(host, path) => write!(f, "http://{host}{path}"),
+        }
+    }
+}
+
+impl TimeSource {
+    /// Seconds the local clock must move forward to agree with the source (negative when the
+    /// local clock is ahead)
+    pub async fn measure_skew(&self, clock: &dyn Clock) -> Result<i64> {
+        let sent = unix_seconds(clock.now());
+        let remote = time::timeout(TIMEOUT, self.query())
+            .await
+            .with_context(|| format!("Timed out querying {self}"))??;
+        let received = unix_seconds(clock.now());
+        // Assume the remote time was read halfway through the round trip
+        Ok((remote - (sent + received) / 2.0).round() as i64)
+    }
+
+    async fn query(&self) -> Result<f64> {
+        match self {
+            TimeSource::Sntp(host) => query_sntp(host).await,
+            TimeSource::Http(host, path) => query_http_date(host, path).await,
+        }
+    }
+}
+
+fn unix_seconds(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or_default()
+}
+
+async fn query_sntp(host: &str) -> Result<f64> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    socket.connect(host).await.with_context(|| format!("Failed to resolve {host}"))?;
+    let mut request = [0u8; 48];
+    // LI = 0, version 4, mode 3 (client)
+    request[0] = 0b00_100_011;
+    socket.send(&request).await?;
+
+    let mut response = [0u8; 48];
+    let len = socket.recv(&mut response).await?;
+    if len < 48 {
+        bail!("SNTP response from {host} is too short");
+    }
+    // Transmit timestamp: 32 bits of seconds and 32 bits of fraction since 1900
+    let seconds = u32::from_be_bytes(response[40..44].try_into()?) as f64;
+    let fraction = u32::from_be_bytes(response[44..48].try_into()?) as f64 / u32::MAX as f64;
+    if seconds == 0.0 {
+        bail!("SNTP server {host} did not send a time");
+    }
+    Ok(seconds + fraction - NTP_UNIX_OFFSET)
+}
+
+async fn query_http_date(host: &str, path: &str) -> Result<f64> {
+    let mut stream = TcpStream::connect(host)
+        .await
+        .with_context(|| format!("Failed to connect to {host}"))?;
+    let request = format!("HEAD {path} HTTP/1.1\r\nHost: {host}\r\nConnection: close\r\n\r\n");
+    stream.write_all(request.as_bytes()).await?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await?;
+
+    let date = response
+        .lines()
+        .take_while(|line| !line.is_empty())
+        .find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.eq_ignore_ascii_case("date").then(|| value.trim())
+        })
+        .ok_or(anyhow!("No Date header in response from {host}"))?;
+    let date = httpdate::parse_http_date(date)
+        .with_context(|| format!("Invalid Date header {date}"))?;
+    // The header only has a precision of a second
+    Ok(unix_seconds(date) + 0.5)
+}
+
+/// Latest clock skew measurement, shared between the checker and the outputs
+#[derive(Debug)]
+pub struct ClockSkew {
+    skew: RwLock<Option<i64>>,
+    max_skew: u64,
+}
+
+impl ClockSkew {
+    pub fn new(max_skew: u64) -> Self {
+        ClockSkew {
+            skew: RwLock::new(None),
+            max_skew,
+        }
+    }
+
+    /// Last measured skew in seconds, None until a measurement succeeded
+    pub fn get(&self) -> Option<i64> {
+        *self.skew.read().unwrap()
+    }
+
+    fn set(&self, skew: i64) {
+        *self.skew.write().unwrap() = Some(skew);
+    }
+
+    /// Warning to display when the skew is large enough to make codes wrong
+    pub fn warning(&self) -> Option<String> {
+        self.get()
+            .filter(|skew| skew.unsigned_abs() > self.max_skew)
+            .map(|skew| {
+                let direction = if skew > 0 { "behind" } else { "ahead" };
+                format!(
+                    "Clock is {}s {direction}, codes may be wrong (see --clock-offset)",
+                    skew.abs()
+                )
+            })
+    }
+}
+
+/// Measures the skew against `source` every `interval` for as long as the task runs
+pub async fn monitor_skew(
+    source: TimeSource,
+    clock: Arc<dyn Clock>,
+    skew: Arc<ClockSkew>,
+    interval: Duration,
+) {
+    let mut interval = time::interval(interval);
+    loop {
+        interval.tick().await;
+        match source.measure_skew(clock.as_ref()).await {
+            Ok(measured) => {
+                skew.set(measured);
+                if let Some(warning) = skew.warning() {
+                    tracing::warn!("{warning}");
+                } else {
+                    tracing::debug!("Clock skew against {source} is {measured}s");
+                }
+            }
+            Err(err) => tracing::warn!("Failed to check clock skew against {source}: {err}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::clock::FakeClock;
+
+    #[test]
+    fn test_parses_sources() {
+        assert_eq!(
+            "sntp://pool.ntp.org".parse::<TimeSource>().unwrap(),
+            TimeSource::Sntp("pool.ntp.org:123".to_owned())
+        );
+        assert_eq!(
+            "http://127.0.0.1:8080".parse::<TimeSource>().unwrap(),
+            TimeSource::Http("127.0.0.1:8080".to_owned(), "/".to_owned())
+        );
+        assert!("ftp://example.com".parse::<TimeSource>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_measures_skew_against_sntp() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let source = TimeSource::Sntp(server.local_addr().unwrap().to_string());
+        tokio::spawn(async move {
+            let mut request = [0u8; 48];
+            let (_, peer) = server.recv_from(&mut request).await.unwrap();
+            let mut response = [0u8; 48];
+            let seconds = (1_748_742_700 + NTP_UNIX_OFFSET as u64) as u32;
+            response[40..44].copy_from_slice(&seconds.to_be_bytes());
+            server.send_to(&response, peer).await.unwrap();
+        });
+
+        let clock = FakeClock::at(1_748_742_637);
+        assert_eq!(source.measure_skew(&clock).await.unwrap(), 63);
+    }
+
+    #[tokio::test]
+    async fn test_measures_skew_against_http_date() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let source = TimeSource::Http(listener.local_addr().unwrap().to_string(), "/".to_owned());
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buffer = [0u8; 1024];
+            let _ = stream.read(&mut buffer).await.unwrap();
+            // 1748742600
+            let response = "HTTP/1.1 200 OK\r\nDate: Sun, 01 Jun 2025 01:50:00 GMT\r\n\r\n";
+            stream.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        let clock = FakeClock::at(1_748_742_637);
+        let skew = ClockSkew::new(5);
+        skew.set(source.measure_skew(&clock).await.unwrap());
+        assert_eq!(skew.get(), Some(-37));
+        assert!(skew.warning().unwrap().contains("37s ahead"));
+    }
+}
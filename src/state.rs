@@ -1,25 +1,34 @@
 use std::{sync::Arc, time::{Duration, SystemTime}};
 
-use crate::config::{configuration::NumberStyle, secrets::ConfigFile};
+use crate::{clock::Clock, config::{configuration::NumberStyle, sources::ConfigSources}, skew::ClockSkew};
 
 pub struct State {
     pub lock_password: Option< String >,
     pub unlocked_since: Option<SystemTime>,
     pub lock_after: Option<Duration>,
-    pub secrets_cf: Arc<ConfigFile>,
+    pub secrets_cf: Arc<ConfigSources>,
     pub buffer: String,
     pub number_style: NumberStyle,
+    pub show_next: u16,
+    pub clock: Arc<dyn Clock>,
+    pub clock_skew: Arc<ClockSkew>,
+    /// Only entries with this tag are shown
+    pub tag_filter: Option<String>,
 }
 
 impl State  {
-   pub fn default(secrets_cf: Arc<ConfigFile>, lock_password: Option<String>, lock_after_seconds: u16, number_style: NumberStyle) -> State {
+   pub fn default(secrets_cf: Arc<ConfigSources>, lock_password: Option<String>, lock_after_seconds: u16, number_style: NumberStyle, show_next: u16, clock: Arc<dyn Clock>, clock_skew: Arc<ClockSkew>) -> State {
        State {
         secrets_cf,
         lock_password,
-        unlocked_since: Some( SystemTime::now() ),
+        unlocked_since: Some( clock.now() ),
         lock_after: if lock_after_seconds > 0 { Some(Duration::from_secs(lock_after_seconds.into())) } else { None },
         buffer: "".to_owned(),
         number_style,
+        show_next,
+        clock,
+        clock_skew,
+        tag_filter: None,
        }
    }
 }
@@ -1,46 +1,265 @@
 use base32::{decode, Alphabet};
+use base64::{engine::general_purpose, Engine as _};
 use hmac::{Hmac, Mac};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use sha1::Sha1;
-use std::{
-    fmt,
-    time::{SystemTime, UNIX_EPOCH},
-};
+use sha2::{Sha256, Sha512};
+use std::fmt;
 
+use zeroize::Zeroizing;
+
+use crate::{clock::Clock, secret::Secret};
+
+/// HMAC hash function used to derive the token, as allowed by RFC 6238
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Algorithm {
+    #[default]
+    #[serde(rename = "SHA1", alias = "sha1")]
+    Sha1,
+    #[serde(rename = "SHA256", alias = "sha256")]
+    Sha256,
+    #[serde(rename = "SHA512", alias = "sha512")]
+    Sha512,
+}
+
+impl Algorithm {
+    fn hmac(&self, key: &[u8], message: &[u8]) -> Vec<u8> {
+        // HMAC accepts keys of any length so new_from_slice cannot fail
+        match self {
+            Algorithm::Sha1 => {
+                let mut mac = Hmac::<Sha1>::new_from_slice(key).unwrap();
+                mac.update(message);
+                mac.finalize().into_bytes().to_vec()
+            }
+            Algorithm::Sha256 => {
+                let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+                mac.update(message);
+                mac.finalize().into_bytes().to_vec()
+            }
+            Algorithm::Sha512 => {
+                let mut mac = Hmac::<Sha512>::new_from_slice(key).unwrap();
+                mac.update(message);
+                mac.finalize().into_bytes().to_vec()
+            }
+        }
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Algorithm::Sha1 => "SHA1",
+            Algorithm::Sha256 => "SHA256",
+            Algorithm::Sha512 => "SHA512",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Scheme of an entry, used to look up its [`OtpGenerator`](crate::generator::OtpGenerator)
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OtpType {
+    /// Derived from the clock (RFC 6238)
+    #[default]
+    Totp,
+    /// Derived from a stored counter (RFC 4226)
+    Hotp,
+    /// Steam Guard: time based, but the token is spelt with [`STEAM_ALPHABET`]
+    Steam,
+    /// Mobile-OTP: MD5 of the time, the secret and a PIN
+    Motp,
+    /// Any other scheme, handled by a generator registered under this name
+    #[serde(untagged)]
+    Other(String),
+}
+
+impl fmt::Display for OtpType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            OtpType::Totp => "totp",
+            OtpType::Hotp => "hotp",
+            OtpType::Steam => "steam",
+            OtpType::Motp => "motp",
+            OtpType::Other(name) => name,
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Characters Steam Guard codes are made of
+pub const STEAM_ALPHABET: &[u8; 26] = b"23456789BCDFGHJKMNPQRTVWXY";
+
+/// How the truncated HMAC is turned into the token shown to the user
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenFormat {
+    /// Zero padded decimal code of the given number of digits
+    Decimal(u8),
+    /// Five characters out of [`STEAM_ALPHABET`]
+    Steam,
+}
+
+/// Division of time into steps as in RFC 6238: step `n` starts at `t0 + n * seconds`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeStep {
+    /// UNIX timestamp at which counting starts
+    pub t0: u64,
+    pub seconds: u64,
+}
+
+impl TimeStep {
+    /// Steps counted from the UNIX epoch, the usual case
+    pub fn seconds(seconds: u64) -> TimeStep {
+        TimeStep { t0: 0, seconds }
+    }
+
+    /// Counter of the step `timestamp` falls into; times before `t0` count as the first step
+    pub fn counter(&self, timestamp: u64) -> u64 {
+        timestamp.saturating_sub(self.t0) / self.seconds
+    }
+
+    /// UNIX timestamp at which the step numbered `counter` ends
+    pub fn end(&self, counter: u64) -> u64 {
+        self.t0.saturating_add(counter.saturating_add(1).saturating_mul(self.seconds))
+    }
+}
+
+/// How the secret is written in the secrets file
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SecretEncoding {
+    #[default]
+    Base32,
+    Hex,
+    Base64,
+    /// The secret is used as is, as done by some hardware tokens and older services
+    Ascii,
+}
+
+impl fmt::Display for SecretEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            SecretEncoding::Base32 => "base32",
+            SecretEncoding::Hex => "hex",
+            SecretEncoding::Base64 => "base64",
+            SecretEncoding::Ascii => "ascii",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Reasons an entry cannot produce a token
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TotpError {
+    /// The secret is empty or not valid in its encoding
+    MalformedSecret(SecretEncoding),
+    /// More digits than the 31 bits of the truncated HMAC can provide, or none at all
+    UnsupportedDigits(u8),
+    /// A time step of 0 seconds
+    ZeroTimestep,
+    /// The scheme requires a PIN and the entry has none
+    MissingPin,
+    /// No generator is registered for the entry's type
+    UnknownType(String),
+    /// The secret is kept outside the entry and could not be read; describes where and why
+    UnresolvedSecret(String),
+    /// More than one of `secret`, `secret_env`, `secret_file` and `secret_command` is set
+    ConflictingSecrets,
+}
+
+impl TotpError {
+    /// Short machine readable identifier of the error
+    pub fn kind(&self) -> &'static str {
+        match self {
+            TotpError::MalformedSecret(_) => "malformed_secret",
+            TotpError::UnsupportedDigits(_) => "unsupported_digits",
+            TotpError::ZeroTimestep => "zero_timestep",
+            TotpError::MissingPin => "missing_pin",
+            TotpError::UnknownType(_) => "unknown_type",
+            TotpError::UnresolvedSecret(_) => "unresolved_secret",
+            TotpError::ConflictingSecrets => "conflicting_secrets",
+        }
+    }
+}
+
+impl fmt::Display for TotpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TotpError::MalformedSecret(encoding) => write!(f, "Secret is not valid {encoding}"),
+            TotpError::UnsupportedDigits(digits) => {
+                write!(f, "Invalid number of digits {digits}, expected 1 to 10")
+            }
+            TotpError::ZeroTimestep => write!(f, "Time step must be greater than 0"),
+            TotpError::MissingPin => write!(f, "A PIN is required for this type of entry"),
+            TotpError::UnknownType(name) => write!(f, "Unknown entry type {name}"),
+            TotpError::UnresolvedSecret(reason) => {
+                write!(f, "Cannot read the secret from {reason}")
+            }
+            TotpError::ConflictingSecrets => write!(
+                f,
+                "Only one of secret, secret_env, secret_file and secret_command can be set"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TotpError {}
+
+/// Checks that a token can be generated with these parameters
+pub fn validate_parameters(time_step: TimeStep, format: TokenFormat) -> Result<(), TotpError> {
+    if time_step.seconds == 0 {
+        return Err(TotpError::ZeroTimestep);
+    }
+    validate_format(format)
+}
+
+fn validate_format(format: TokenFormat) -> Result<(), TotpError> {
+    match format {
+        TokenFormat::Decimal(digits) if !(1..=10).contains(&digits) => {
+            Err(TotpError::UnsupportedDigits(digits))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// A generated token along with the counter it was derived from
 #[derive(Serialize, Debug)]
 pub struct Totp {
+    /// UNIX timestamp at which the token expires, 0 for HOTP tokens
     pub valid_until: u64,
     pub token: String,
+    /// Token of the following time step, so it can be typed without waiting for the rollover;
+    /// `None` for HOTP tokens
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_token: Option<String>,
     counter: u64,
 }
 impl Totp {
-    pub fn new(secret: &str, time_step: u16, digits: u8) -> Totp {
-        let mut totp = Totp {
-            valid_until: 0,
-            token: String::new(),
-            counter: 0,
-        };
-        totp.refresh(secret, time_step, digits);
-        totp
+    /// Token built by a generator that derived it itself
+    pub(crate) fn from_parts(
+        token: String,
+        next_token: Option<String>,
+        counter: u64,
+        valid_until: u64,
+    ) -> Totp {
+        Totp {
+            valid_until,
+            token,
+            next_token,
+            counter,
+        }
     }
-    pub fn valid_duration(&self) -> u16 {
-        (self.valid_until
-            - SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs()) as u16
+
+    pub fn counter(&self) -> u64 {
+        self.counter
     }
 
-    pub fn needs_refresh(&self, time_step: u16) -> bool {
-        let new_counter = get_counter(None, time_step);
-        new_counter != self.counter
+    pub fn valid_duration(&self, clock: &dyn Clock) -> u64 {
+        self.valid_until.saturating_sub(clock.unix_timestamp())
     }
 
-    pub fn refresh(&mut self, secret: &str, time_step: u16, digits: u8) {
-        let (otp, valid_until, counter) = generate_totp(secret, time_step, digits, None);
-        self.token = otp;
-        self.valid_until = valid_until;
-        self.counter = counter;
+    pub fn needs_refresh(&self, time_step: TimeStep, clock: &dyn Clock) -> bool {
+        time_step.counter(clock.unix_timestamp()) != self.counter
     }
 }
 
@@ -50,31 +269,68 @@ impl fmt::Display for Totp {
     }
 }
 
-fn get_counter(timestamp: Option<u64>, time_step: u16) -> u64 {
-    let current_time = timestamp.unwrap_or_else(|| {
-        SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs()
-    });
-    let time_step = time_step as u64; 
-    current_time / time_step
+/// Compares without short-circuiting so that response time does not leak how many leading
+/// characters were right
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
 }
 
-fn generate_totp(
+/// Canonical form of a base32 secret as handed out by providers, e.g. `jbsw y3dp ehpk 3pxp` or
+/// `JBSWY3DPEHPK3PXP====` both become `JBSWY3DPEHPK3PXP`
+pub fn normalize_base32(secret: &str) -> String {
+    secret
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-' && *c != '=')
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+/// Decodes a secret into the HMAC key
+pub fn decode_secret(
     secret: &str,
-    time_step: u16,
-    digits: u8,
-    timestamp: Option<u64>,
-) -> (String, u64, u64) {
-    // Decode Base32 secret
-    let secret_bytes =
-        decode(Alphabet::RFC4648 { padding: false }, secret).expect("Invalid base32 secret");
-
-    // Time counter (moving factor)
-    let counter = get_counter(timestamp, time_step);
-    let time_step = time_step as u64;
-    let valid_until = (counter + 1) * time_step;
+    encoding: SecretEncoding,
+) -> Result<Secret<Vec<u8>>, TotpError> {
+    // Intermediate copies of the secret are wiped as well
+    let compact = || Zeroizing::new(secret.split_whitespace().collect::<String>());
+    let bytes = match encoding {
+        SecretEncoding::Base32 => decode(
+            Alphabet::RFC4648 { padding: false },
+            &Zeroizing::new(normalize_base32(secret)),
+        ),
+        SecretEncoding::Hex => {
+            let hex = compact();
+            let hex = hex
+                .strip_prefix("0x")
+                .or_else(|| hex.strip_prefix("0X"))
+                .unwrap_or(&hex);
+            decode_hex(hex)
+        }
+        SecretEncoding::Base64 => general_purpose::STANDARD.decode(compact().as_str()).ok(),
+        SecretEncoding::Ascii => Some(secret.as_bytes().to_vec()),
+    };
+    bytes
+        .map(Secret::new)
+        .filter(|bytes| !bytes.expose().is_empty())
+        .ok_or(TotpError::MalformedSecret(encoding))
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+pub(crate) fn generate_hotp(
+    key: &[u8],
+    counter: u64,
+    format: TokenFormat,
+    algorithm: Algorithm,
+) -> Result<String, TotpError> {
+    validate_format(format)?;
 
     // Convert counter to big-endian byte array
     let mut counter_bytes = [0u8; 8];
@@ -82,59 +338,245 @@ fn generate_totp(
         counter_bytes[i] = *byte;
     }
 
-    // HMAC-SHA1
-    let mut mac = Hmac::<Sha1>::new_from_slice(&secret_bytes).unwrap();
-    mac.update(&counter_bytes);
-    let hmac_result = mac.finalize().into_bytes();
+    // HMAC-SHA1/SHA256/SHA512
+    let hmac_result = algorithm.hmac(key, &counter_bytes);
 
-    // Dynamic Truncation
-    let offset = (hmac_result[19] & 0x0f) as usize;
+    // Dynamic Truncation: offset comes from the low nibble of the last byte, whatever the digest
+    // length
+    let offset = (hmac_result[hmac_result.len() - 1] & 0x0f) as usize;
     let binary_code = ((hmac_result[offset] as u32 & 0x7f) << 24)
         | ((hmac_result[offset + 1] as u32) << 16)
         | ((hmac_result[offset + 2] as u32) << 8)
         | (hmac_result[offset + 3] as u32);
 
-    // 6-digit code
-    let otp = binary_code % 10u32.pow(digits as u32);
-    (
-        format!("{:0digits$}", otp, digits = digits as usize),
-        valid_until,
-        counter,
-    )
+    Ok(match format {
+        TokenFormat::Decimal(digits) => {
+            let otp = binary_code as u64 % 10u64.pow(digits as u32);
+            format!("{:0digits$}", otp, digits = digits as usize)
+        }
+        TokenFormat::Steam => {
+            // Least significant "digit" first, in base 26
+            let mut remainder = binary_code as usize;
+            (0..5)
+                .map(|_| {
+                    let ch = STEAM_ALPHABET[remainder % STEAM_ALPHABET.len()] as char;
+                    remainder /= STEAM_ALPHABET.len();
+                    ch
+                })
+                .collect()
+        }
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use crate::{
+        clock::FakeClock,
+        config::secrets::ConfigEntry,
+        generator::{self, OtpGenerator, Rfc6238},
+    };
+
+    fn entry(secret: &str, encoding: SecretEncoding) -> ConfigEntry {
+        let mut entry = ConfigEntry::new("Test".to_owned(), secret.to_owned());
+        entry.encoding = encoding;
+        entry
+    }
+
     #[test]
     fn test_known_totp_values() {
-        let secret = "JBSWY3DPEHPK3PXP"; // This is not a real secret, just something random
-        let digits = 6;
-        let time_step = 30;
+        // This is not a real secret, just something random
+        let entry = entry("JBSWY3DPEHPK3PXP", SecretEncoding::Base32);
 
         let test_cases = vec![
-            (1748742637, "879599", 1748742659, 58291421), // 1748742637 / 30 = 58291421
-            (1748742663, "690726", 1748742679, 58291422),
-            (1748742688, "690726", 1748742679, 58291422),
-            (1748742714, "565959", 1748742739, 58291423),
-            (1748742739, "295060", 1748742739, 58291424),
+            (1748742637, "879599", 1748742660, 58291421), // 1748742637 / 30 = 58291421
+            (1748742663, "690726", 1748742690, 58291422),
+            (1748742688, "690726", 1748742690, 58291422),
+            (1748742714, "565959", 1748742720, 58291423),
+            (1748742739, "295060", 1748742750, 58291424),
         ];
 
         for (timestamp, expected, expected_valid, expected_counter) in test_cases {
-            let (otp, valid, counter) =
-                generate_totp(secret, time_step, digits, Some(timestamp));
-            assert_eq!(otp, expected, "Failed for timestamp {}", timestamp);
+            let totp = generator::generate(&entry, &FakeClock::at(timestamp)).unwrap();
+            assert_eq!(totp.token, expected, "Failed for timestamp {}", timestamp);
             assert_eq!(
-                valid, expected_valid,
+                totp.valid_until, expected_valid,
                 "Failed valid for timestamp {}",
                 timestamp
             );
             assert_eq!(
-                counter, expected_counter,
+                totp.counter(),
+                expected_counter,
                 "Failed counter for timestamp {}",
                 timestamp
             );
         }
     }
+
+    #[test]
+    fn test_verify_totp_window() {
+        let entry = entry("JBSWY3DPEHPK3PXP", SecretEncoding::Base32);
+        // 690726 is the code of counter 58291422
+        let verify =
+            |timestamp, skew| generator::verify(&entry, "690726", skew, timestamp).unwrap();
+        assert_eq!(verify(1748742663, 0), Some(58291422));
+        assert_eq!(verify(1748742637, 0), None);
+        assert_eq!(verify(1748742637, 1), Some(58291422));
+        assert_eq!(verify(1748742714, 1), Some(58291422));
+        assert_eq!(verify(1748742739, 1), None);
+        assert_eq!(
+            generator::verify(&entry, "690727", 1, 1748742663),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn test_rfc4226_vectors() {
+        // RFC 4226 Appendix D
+        let mut entry = entry("12345678901234567890", SecretEncoding::Ascii);
+        entry.otp_type = OtpType::Hotp;
+        let expected = [
+            "755224", "287082", "359152", "969429", "338314", "254676", "287922", "162583",
+            "399871", "520489",
+        ];
+        for (counter, expected) in expected.iter().enumerate() {
+            entry.counter = counter as u64;
+            let totp = generator::generate(&entry, &FakeClock::at(0)).unwrap();
+            assert_eq!(totp.token, *expected, "Failed for counter {}", counter);
+            assert_eq!(totp.counter(), counter as u64);
+        }
+    }
+
+    #[test]
+    fn test_rfc6238_vectors() {
+        // RFC 6238 Appendix B: the seed is the ASCII string "1234567890" repeated to the digest
+        // length of each hash
+        let sha1_seed: &[u8] = b"12345678901234567890";
+        let sha256_seed: &[u8] = b"12345678901234567890123456789012";
+        let sha512_seed: &[u8] =
+            b"1234567890123456789012345678901234567890123456789012345678901234";
+
+        let test_cases = vec![
+            (59, "94287082", "46119246", "90693936"),
+            (1111111109, "07081804", "68084774", "25091201"),
+            (1111111111, "14050471", "67062674", "99943326"),
+            (1234567890, "89005924", "91819424", "93441116"),
+            (2000000000, "69279037", "90698825", "38618901"),
+            (20000000000, "65353130", "77737706", "47863826"),
+        ];
+
+        for (timestamp, sha1, sha256, sha512) in test_cases {
+            for (seed, algorithm, expected) in [
+                (sha1_seed, Algorithm::Sha1, sha1),
+                (sha256_seed, Algorithm::Sha256, sha256),
+                (sha512_seed, Algorithm::Sha512, sha512),
+            ] {
+                let mut entry = entry("", SecretEncoding::Ascii);
+                entry.digits = 8;
+                entry.algorithm = algorithm;
+                let counter = entry.time_step().counter(timestamp);
+                let otp = Rfc6238.token(&entry, seed, counter).unwrap();
+                assert_eq!(
+                    otp, expected,
+                    "Failed {} for timestamp {}",
+                    algorithm, timestamp
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_steam_tokens() {
+        let mut entry = entry("SGVsbG8h3q2+7w==", SecretEncoding::Base64);
+        entry.otp_type = OtpType::Steam;
+        for (timestamp, expected) in [(59, "2YXGV"), (1748742637, "3WJRM"), (1748742663, "B3KKK")] {
+            let totp = generator::generate(&entry, &FakeClock::at(timestamp)).unwrap();
+            assert_eq!(totp.token, expected, "Failed for timestamp {}", timestamp);
+        }
+    }
+
+    #[test]
+    fn test_time_step_epoch() {
+        let daily = TimeStep {
+            t0: 1000,
+            seconds: 86400,
+        };
+        assert_eq!(daily.counter(0), 0);
+        assert_eq!(daily.counter(87399), 0);
+        assert_eq!(daily.counter(87400), 1);
+        assert_eq!(daily.end(1), 173800);
+
+        // Same code as counter 58291422 from the epoch, but the step ends 15 seconds later
+        let mut shifted = entry("JBSWY3DPEHPK3PXP", SecretEncoding::Base32);
+        shifted.t0 = 15;
+        let totp = generator::generate(&shifted, &FakeClock::at(1748742677)).unwrap();
+        assert_eq!(
+            (totp.token.as_str(), totp.valid_until, totp.counter()),
+            ("690726", 1748742705, 58291422)
+        );
+    }
+
+    #[test]
+    fn test_invalid_parameters() {
+        let generate = |timestep, digits| {
+            let mut entry = entry("JBSWY3DPEHPK3PXP", SecretEncoding::Base32);
+            entry.timestep = timestep;
+            entry.digits = digits;
+            generator::generate(&entry, &FakeClock::at(59)).map(|_| ())
+        };
+        assert_eq!(generate(0, 6), Err(TotpError::ZeroTimestep));
+        assert_eq!(generate(30, 0), Err(TotpError::UnsupportedDigits(0)));
+        assert_eq!(generate(30, 11), Err(TotpError::UnsupportedDigits(11)));
+        assert_eq!(generate(30, 10), Ok(()));
+    }
+
+    #[test]
+    fn test_lenient_secret_parsing() {
+        let expected = Secret::new(b"Hello!\xde\xad\xbe\xef".to_vec());
+        for secret in [
+            "JBSWY3DPEHPK3PXP",
+            "jbsw y3dp ehpk 3pxp",
+            "JBSWY3DPEHPK3PXP====",
+            "JBSW-Y3DP-EHPK-3PXP",
+        ] {
+            assert_eq!(
+                decode_secret(secret, SecretEncoding::Base32),
+                Ok(expected.clone()),
+                "Failed for {}",
+                secret
+            );
+        }
+        assert_eq!(
+            decode_secret("48656c6c 6f21DEAD beef", SecretEncoding::Hex),
+            Ok(expected.clone())
+        );
+        assert_eq!(
+            decode_secret("0x48656c6c6f21deadbeef", SecretEncoding::Hex),
+            Ok(expected.clone())
+        );
+        assert_eq!(
+            decode_secret("SGVsbG8h3q2+7w==", SecretEncoding::Base64),
+            Ok(expected)
+        );
+        assert_eq!(
+            decode_secret("12345678901234567890", SecretEncoding::Ascii),
+            Ok(Secret::new(b"12345678901234567890".to_vec()))
+        );
+
+        for (secret, encoding) in [
+            ("JBSWY3DP!", SecretEncoding::Base32),
+            ("", SecretEncoding::Base32),
+            ("48656c6", SecretEncoding::Hex),
+            ("zz", SecretEncoding::Hex),
+            ("SGVsbG8h3q2+7w=", SecretEncoding::Base64),
+        ] {
+            assert_eq!(
+                decode_secret(secret, encoding),
+                Err(TotpError::MalformedSecret(encoding)),
+                "Failed for {}",
+                secret
+            );
+        }
+    }
 }
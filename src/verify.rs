@@ -0,0 +1,107 @@
+use std::{fmt, sync::Arc};
+
+use anyhow::{Result, bail};
+use serde::Serialize;
+
+use crate::{
+    clock::Clock,
+    config::secrets::ConfigEntry,
+    generator,
+};
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+#[serde(tag = "outcome", rename_all = "lowercase")]
+pub enum Verification {
+    /// `drift` is how many steps away from the current counter the code was found
+    Accepted { counter: u64, drift: i64 },
+    Rejected,
+    /// The code is right but a code for the same or a later counter was already accepted
+    Replayed,
+}
+
+impl Verification {
+    pub fn is_accepted(&self) -> bool {
+        matches!(self, Verification::Accepted { .. })
+    }
+}
+
+impl fmt::Display for Verification {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Verification::Accepted { counter, drift } => {
+                write!(f, "Valid for counter {counter} (drift {drift})")
+            }
+            Verification::Rejected => write!(f, "Invalid code"),
+            Verification::Replayed => write!(f, "Code already used"),
+        }
+    }
+}
+
+/// Verifies submitted codes. A code for the entry's `last_verified` time step or an earlier one is
+/// refused, [`ConfigFile::verify`](crate::config::secrets::ConfigFile::verify) records it in the
+/// secrets file so that a code cannot be used twice
+pub struct Verifier {
+    skew: u8,
+    clock: Arc<dyn Clock>,
+}
+
+impl Verifier {
+    pub fn new(skew: u8, clock: Arc<dyn Clock>) -> Self {
+        Verifier { skew, clock }
+    }
+
+    pub fn verify(&self, entry: &ConfigEntry, code: &str) -> Result<Verification> {
+        if entry.generator()?.time_step(entry).is_none() {
+            bail!("Verification is only supported for time based entries");
+        }
+        let Some(counter) =
+            generator::verify(entry, code.trim(), self.skew, self.clock.unix_timestamp())?
+        else {
+            return Ok(Verification::Rejected);
+        };
+
+        if entry.last_verified.is_some_and(|last| counter <= last) {
+            tracing::warn!("Replayed code for {}", entry.name);
+            return Ok(Verification::Replayed);
+        }
+        let drift = counter as i64 - entry.totp(self.clock.as_ref())?.counter() as i64;
+        Ok(Verification::Accepted { counter, drift })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{clock::FakeClock, config::secrets::ConfigFile};
+
+    #[tokio::test]
+    async fn test_rejects_replayed_codes() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("secrets.json");
+        // Two entries without a handle and with the same name keep apart what they accepted
+        let entry = r#"{"name": "Test", "secret": "JBSWY3DPEHPK3PXP"}"#;
+        std::fs::write(&path, format!("[{entry}, {entry}]")).unwrap();
+        let clock = Arc::new(FakeClock::at(1748742663));
+        let verifier = Verifier::new(1, clock.clone());
+        let path = path.to_string_lossy().into_owned();
+        let secrets_cf = ConfigFile::new(path.clone());
+        let (_, entries) = secrets_cf.load().await.unwrap();
+        let token = entries[0].totp(clock.as_ref()).unwrap().token;
+        let verify = async |cf: &ConfigFile, index: usize, code: &str| {
+            cf.verify(index, &entries[index], code, &verifier).await.unwrap()
+        };
+
+        assert!(verify(&secrets_cf, 0, &token).await.is_accepted());
+        assert_eq!(verify(&secrets_cf, 0, &token).await, Verification::Replayed);
+        assert_eq!(
+            verify(&secrets_cf, 0, "not a code").await,
+            Verification::Rejected
+        );
+        assert!(verify(&secrets_cf, 1, &token).await.is_accepted());
+
+        // The accepted time step is kept in the file, so a later run refuses the code too
+        let later_run = ConfigFile::new(path);
+        later_run.load().await.unwrap();
+        assert_eq!(verify(&later_run, 1, &token).await, Verification::Replayed);
+    }
+}